@@ -3,14 +3,14 @@ use quote::quote;
 
 /// See the `fn call` docs at the `lib.rs` of this crate for a high level definition.
 pub fn expand_call(def: CallDef) -> proc_macro2::TokenStream {
-	let CallDef { pallet_struct, methods } = def;
+	let CallDef { pallet_struct, methods, custom_hooks } = def;
 
 	// This is a vector of all the callable function names.
 	let fn_name = methods.iter().map(|method| &method.name).collect::<Vec<_>>();
 
 	// This is a nested vector of all the arguments for each of the functions in `fn_name`. It does
-	// not include the `self` or `caller: T::AccountId` parameter, which we always assume are the
-	// first two parameters to these calls.
+	// not include the `self` or `origin: T::RuntimeOrigin` parameter, which we always assume are
+	// the first two parameters to these calls.
 	let args_name = methods
 		.iter()
 		.map(|method| method.args.iter().map(|(name, _)| name.clone()).collect::<Vec<_>>())
@@ -23,32 +23,109 @@ pub fn expand_call(def: CallDef) -> proc_macro2::TokenStream {
 		.map(|method| method.args.iter().map(|(_, type_)| type_.clone()).collect::<Vec<_>>())
 		.collect::<Vec<_>>();
 
+	// A description of each type in `args_type` for the metadata, written as it appears in the
+	// pallet so it does not depend on the compiler's naming of the concrete runtime type.
+	let args_type_name = args_type
+		.iter()
+		.map(|types| types.iter().map(|type_| type_description(type_)).collect::<Vec<_>>())
+		.collect::<Vec<_>>();
+
+	// The index of each call in the `Call` enum, which identifies it in the encoding of the call.
+	let fn_index = (0..methods.len()).map(|i| i as u8).collect::<Vec<_>>();
+
+	// The doc comments of each of the functions in `fn_name`.
+	let docs = methods.iter().map(|method| &method.docs).collect::<Vec<_>>();
+
+	// The weight expression of each of the functions in `fn_name`.
+	let weight = methods.iter().map(|method| &method.weight).collect::<Vec<_>>();
+
+	// Every type used by an argument, which must be encodable for `Call` to be encodable.
+	let all_args_type = args_type.iter().flatten().collect::<Vec<_>>();
+
 	// This quote block creates an `enum Call` which contains all the calls exposed by our pallet,
-	// and the `Dispatch` trait logic to route a `caller` to access those functions.
+	// and the `Dispatch` trait logic to route an `origin` to access those functions.
 	let dispatch_impl = quote! {
 		// The callable functions exposed by this pallet.
 		//
 		// The parsed function names will be `snake_case`, and that will show up in the enum.
 		#[allow(non_camel_case_types)]
+		#[derive(Debug, Clone)]
 		pub enum Call<T: Config> {
 			#(
 				#fn_name { #( #args_name: #args_type),* },
 			)*
 		}
 
+		// The weight of each call is declared by the `#[weight(expr)]` attribute of its function. The
+		// expression can use the arguments of the call, by reference.
+		impl<T: Config> crate::support::GetWeight for Call<T> {
+			#[allow(unused_variables)]
+			fn weight(&self) -> crate::support::Weight {
+				match self {
+					#(
+						Call::#fn_name { #( #args_name ),* } => #weight,
+					)*
+				}
+			}
+		}
+
+		// `Call` is encoded as the index of the function, followed by its arguments. The index is
+		// the position of the function in the pallet, so it is stable as long as functions are only
+		// added at the end.
+		impl<T: Config> crate::support::codec::Encode for Call<T>
+		where
+			#( #all_args_type: crate::support::codec::Encode ),*
+		{
+			fn encode_to(&self, dest: &mut Vec<u8>) {
+				match self {
+					#(
+						Call::#fn_name { #( #args_name ),* } => {
+							dest.push(#fn_index);
+							#( crate::support::codec::Encode::encode_to(#args_name, dest); )*
+						},
+					)*
+				}
+			}
+		}
+
+		impl<T: Config> crate::support::codec::Decode for Call<T>
+		where
+			#( #all_args_type: crate::support::codec::Decode ),*
+		{
+			fn decode(input: &mut &[u8]) -> Result<Self, crate::support::codec::DecodeError> {
+				match <u8 as crate::support::codec::Decode>::decode(input)? {
+					#(
+						#fn_index => Ok(Call::#fn_name {
+							#( #args_name: crate::support::codec::Decode::decode(input)?, )*
+						}),
+					)*
+					_ => Err(crate::support::codec::DecodeError("invalid call index")),
+				}
+			}
+		}
+
+		// Every callable pallet declares an `enum Error` next to its calls. We convert it into the
+		// runtime wide `DispatchError`, using the position of the variant as its index. The
+		// `pallet_index` is filled in by the runtime, since only it knows where the pallet lives.
+		impl From<Error> for crate::support::DispatchError {
+			fn from(error: Error) -> Self {
+				crate::support::DispatchError::Module { pallet_index: 0, error_index: error as u8 }
+			}
+		}
+
 		// Dispatch logic at the pallet level, mapping each of the items in the `Call` enum to the
-		// appropriate function call with all arguments, including the `caller`.
+		// appropriate function call with all arguments, including the `origin`.
 		impl<T: Config> crate::support::Dispatch for #pallet_struct<T> {
-			type Caller = T::AccountId;
+			type Origin = T::RuntimeOrigin;
 			type Call = Call<T>;
 
-			fn dispatch(&mut self, caller: Self::Caller, call: Self::Call) -> crate::support::DispatchResult {
+			fn dispatch(&mut self, origin: Self::Origin, call: Self::Call) -> crate::support::DispatchResult {
 				match call {
 					#(
 						Call::#fn_name { #( #args_name ),* } => {
 							self.#fn_name(
-								// Note that we assume the first argument of every call is the `caller`.
-								caller,
+								// Note that we assume the first argument of every call is the `origin`.
+								origin,
 								#( #args_name ),*
 							)?;
 						},
@@ -59,6 +136,73 @@ pub fn expand_call(def: CallDef) -> proc_macro2::TokenStream {
 		}
 	};
 
+	// This quote block describes the callable functions and the errors of the pallet, so that they
+	// can be part of the metadata of the runtime.
+	let metadata_impl = quote! {
+		impl<T: Config> #pallet_struct<T> {
+			// The `name` and `index` of the pallet are filled in by the runtime.
+			pub fn metadata() -> crate::support::metadata::PalletMetadata {
+				crate::support::metadata::PalletMetadata {
+					name: "",
+					index: 0,
+					calls: vec![
+						#(
+							crate::support::metadata::CallMetadata {
+								name: stringify!(#fn_name),
+								index: #fn_index,
+								args: vec![
+									#(
+										crate::support::metadata::ArgMetadata {
+											name: stringify!(#args_name),
+											ty: #args_type_name,
+										}
+									),*
+								],
+								docs: vec![ #( #docs ),* ],
+							}
+						),*
+					],
+					errors: Error::metadata(),
+				}
+			}
+		}
+	};
+
+	// Most pallets have nothing to do at block boundaries, so unless the pallet implements its own
+	// hooks, it gets the hooks which do nothing.
+	let hooks_impl = if custom_hooks {
+		quote! {}
+	} else {
+		quote! {
+			impl<T: Config> crate::support::Hooks<T::BlockNumber> for #pallet_struct<T> {}
+		}
+	};
+
 	// Return the generated code.
-	dispatch_impl.into()
+	quote! {
+		#dispatch_impl
+		#metadata_impl
+		#hooks_impl
+	}
+	.into()
+}
+
+/// Render `ty` as source text, keeping a space only where it separates two words (as in
+/// `dyn Trait`), so that `T::AccountId` is described as such rather than as `T :: AccountId`.
+fn type_description(ty: &syn::Type) -> String {
+	let tokens = quote!(#ty).to_string();
+	let is_word = |c: char| c.is_alphanumeric() || c == '_';
+	let mut description = String::with_capacity(tokens.len());
+	let mut chars = tokens.chars().peekable();
+	while let Some(c) = chars.next() {
+		if c == ' ' {
+			let before = description.chars().last().is_some_and(is_word);
+			let after = chars.peek().copied().is_some_and(is_word);
+			if !(before && after) {
+				continue;
+			}
+		}
+		description.push(c);
+	}
+	description
 }
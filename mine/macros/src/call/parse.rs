@@ -4,7 +4,8 @@ use syn::spanned::Spanned;
 // Custom keywords we match to when parsing the calls in a pallet.
 mod keyword {
 	syn::custom_keyword!(T);
-	syn::custom_keyword!(AccountId);
+	syn::custom_keyword!(RuntimeOrigin);
+	syn::custom_keyword!(hooks);
 }
 
 /// This object will collect all the information we need to keep while parsing the callable
@@ -16,6 +17,9 @@ pub struct CallDef {
 	pub pallet_struct: syn::Ident,
 	/// This is a list of the callable functions exposed by this pallet. See `CallVariantDef`.
 	pub methods: Vec<CallVariantDef>,
+	/// Whether the pallet implements `support::Hooks` itself, as declared with
+	/// `#[macros::call(hooks)]`.
+	pub custom_hooks: bool,
 }
 
 /// This is the metadata we keep about each callable function in our pallet.
@@ -25,10 +29,25 @@ pub struct CallVariantDef {
 	pub name: syn::Ident,
 	/// Information on args of the function: `(name, type)`.
 	pub args: Vec<(syn::Ident, Box<syn::Type>)>,
+	/// The expression given in the `#[weight(expr)]` attribute of the function.
+	pub weight: syn::Expr,
+	/// The doc comments of the function, one entry per line.
+	pub docs: Vec<String>,
 }
 
 impl CallDef {
-	pub fn try_from(item: syn::Item) -> syn::Result<Self> {
+	/// Parse the callable functions of `item`, and the arguments `attr` of the attribute. The
+	/// `#[weight]` attributes are removed from `item`, since they are not real attributes and would
+	/// not compile.
+	pub fn try_from(attr: proc_macro2::TokenStream, item: &mut syn::Item) -> syn::Result<Self> {
+		// The only argument the attribute accepts is `hooks`.
+		let custom_hooks = if attr.is_empty() {
+			false
+		} else {
+			syn::parse2::<keyword::hooks>(attr)?;
+			true
+		};
+
 		// First we check that we are parsing an `impl`.
 		let item_impl = if let syn::Item::Impl(item) = item {
 			item
@@ -45,7 +64,7 @@ impl CallDef {
 
 		// Here is where we will store all the callable functions.
 		let mut methods = vec![];
-		for item in item_impl.items {
+		for item in item_impl.items.iter_mut() {
 			if let syn::ImplItem::Fn(method) = item {
 				// Here is where we will store all the args for each callable functions.
 				let mut args = vec![];
@@ -59,22 +78,26 @@ impl CallDef {
 					},
 				}
 
-				// The second argument should be the `caller: T::AccountId` argument.
+				// The second argument should be the `origin: T::RuntimeOrigin` argument.
 				match method.sig.inputs.iter().skip(1).next() {
 					Some(syn::FnArg::Typed(arg)) => {
 						// Here we specifically check that this argument is as we expect for
-						// `caller: T::AccountId`.
-						check_caller_arg(arg)?;
+						// `origin: T::RuntimeOrigin`.
+						check_origin_arg(arg)?;
 					},
 					_ => {
-						let msg = "Invalid call, second argument should be `caller: T::AccountId`";
+						let msg =
+							"Invalid call, second argument should be `origin: T::RuntimeOrigin`";
 						return Err(syn::Error::new(method.sig.span(), msg))
 					},
 				}
 
 				let fn_name = method.sig.ident.clone();
 
-				// Parsing the rest of the args. Skipping 2 for `self` and `caller`.
+				// Every callable function must declare its weight with `#[weight(expr)]`.
+				let weight = take_weight_attr(method)?;
+
+				// Parsing the rest of the args. Skipping 2 for `self` and `origin`.
 				for arg in method.sig.inputs.iter().skip(2) {
 					// All arguments should be typed.
 					let arg = if let syn::FnArg::Typed(arg) = arg {
@@ -96,42 +119,79 @@ impl CallDef {
 				}
 
 				// Store all the function name and the arg data for the function.
-				methods.push(CallVariantDef { name: fn_name, args });
+				let docs = get_docs(&method.attrs);
+				methods.push(CallVariantDef { name: fn_name, args, weight, docs });
 			}
 		}
 
 		// Return all callable functions for this pallet.
-		Ok(Self { pallet_struct, methods })
+		Ok(Self { pallet_struct, methods, custom_hooks })
+	}
+}
+
+/// Collect the doc comments in `attrs`, one entry per line, without the leading space.
+pub fn get_docs(attrs: &[syn::Attribute]) -> Vec<String> {
+	attrs
+		.iter()
+		.filter_map(|attr| match &attr.meta {
+			syn::Meta::NameValue(meta) if meta.path.is_ident("doc") => match &meta.value {
+				syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(doc), .. }) => Some(doc.value()),
+				_ => None,
+			},
+			_ => None,
+		})
+		.map(|doc| doc.strip_prefix(' ').unwrap_or(&doc).to_string())
+		.collect()
+}
+
+/// Remove the `#[weight(expr)]` attribute from `method`, and return `expr`.
+///
+/// Exactly one such attribute is expected on every callable function.
+pub fn take_weight_attr(method: &mut syn::ImplItemFn) -> syn::Result<syn::Expr> {
+	let (weight_attrs, attrs): (Vec<_>, Vec<_>) =
+		method.attrs.drain(..).partition(|attr| attr.path().is_ident("weight"));
+	method.attrs = attrs;
+
+	match weight_attrs.as_slice() {
+		[attr] => attr.parse_args::<syn::Expr>(),
+		[] => {
+			let msg = "Invalid call, missing `#[weight(expr)]` attribute";
+			Err(syn::Error::new(method.sig.span(), msg))
+		},
+		[_, attr, ..] => {
+			let msg = "Invalid call, duplicate `#[weight(expr)]` attribute";
+			Err(syn::Error::new(attr.span(), msg))
+		},
 	}
 }
 
-/// Check caller arg is exactly: `caller: T::AccountId`.
+/// Check origin arg is exactly: `origin: T::RuntimeOrigin`.
 ///
 /// This is kept strict to keep the code simple.
-pub fn check_caller_arg(arg: &syn::PatType) -> syn::Result<()> {
+pub fn check_origin_arg(arg: &syn::PatType) -> syn::Result<()> {
 	pub struct CheckDispatchableFirstArg;
 	impl syn::parse::Parse for CheckDispatchableFirstArg {
 		fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
 			input.parse::<keyword::T>()?;
 			input.parse::<syn::Token![::]>()?;
-			input.parse::<keyword::AccountId>()?;
+			input.parse::<keyword::RuntimeOrigin>()?;
 			Ok(Self)
 		}
 	}
 
-	// This checks the arg name is `caller` or `_caller`.
+	// This checks the arg name is `origin` or `_origin`.
 	if let syn::Pat::Ident(ident) = &*arg.pat {
-		// We also support the name as `_caller` for when the variable is unused.
-		if &ident.ident != "caller" && &ident.ident != "_caller" {
-			let msg = "Invalid name for second parameter: expected `caller: T::AccountId`";
+		// We also support the name as `_origin` for when the variable is unused.
+		if &ident.ident != "origin" && &ident.ident != "_origin" {
+			let msg = "Invalid name for second parameter: expected `origin: T::RuntimeOrigin`";
 			return Err(syn::Error::new(ident.span(), msg))
 		}
 	}
 
-	// This checks the type is `T::AccountId` with `CheckDispatchableFirstArg`
+	// This checks the type is `T::RuntimeOrigin` with `CheckDispatchableFirstArg`
 	let ty = &arg.ty;
 	syn::parse2::<CheckDispatchableFirstArg>(ty.to_token_stream()).map_err(|e| {
-		let msg = "Invalid type for second parameter: expected `caller: T::AccountId`";
+		let msg = "Invalid type for second parameter: expected `origin: T::RuntimeOrigin`";
 		let mut err = syn::Error::new(ty.span(), msg);
 		err.combine(e);
 		err
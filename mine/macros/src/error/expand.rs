@@ -0,0 +1,33 @@
+use super::parse::ErrorDef;
+use quote::quote;
+
+/// See the `fn error` docs at the `lib.rs` of this crate for a high level definition.
+pub fn expand_error(def: ErrorDef) -> proc_macro2::TokenStream {
+	let ErrorDef { error_enum, variants } = def;
+
+	// This is a vector of all the variant names.
+	let name = variants.iter().map(|variant| &variant.name).collect::<Vec<_>>();
+	// This is a nested vector of the doc comments of each variant in `name`.
+	let docs = variants.iter().map(|variant| &variant.docs).collect::<Vec<_>>();
+
+	// This quote block describes each variant of the error enum, so that it can be part of the
+	// metadata of the runtime.
+	let metadata_impl = quote! {
+		impl #error_enum {
+			pub fn metadata() -> Vec<crate::support::metadata::ErrorMetadata> {
+				vec![
+					#(
+						crate::support::metadata::ErrorMetadata {
+							name: stringify!(#name),
+							index: #error_enum::#name as u8,
+							docs: vec![ #( #docs ),* ],
+						}
+					),*
+				]
+			}
+		}
+	};
+
+	// Return the generated code.
+	metadata_impl
+}
@@ -0,0 +1,45 @@
+use syn::spanned::Spanned;
+
+/// This object will collect all the information we need to keep while parsing the errors of a
+/// pallet.
+#[derive(Debug)]
+pub struct ErrorDef {
+	/// This is the name of the error enum. We mostly assume it is `Error`.
+	pub error_enum: syn::Ident,
+	/// This is a list of the variants of the error enum. See `ErrorVariantDef`.
+	pub variants: Vec<ErrorVariantDef>,
+}
+
+/// This is the metadata we keep about each variant of the error enum.
+#[derive(Debug)]
+pub struct ErrorVariantDef {
+	/// The variant name.
+	pub name: syn::Ident,
+	/// The doc comments of the variant, one entry per line.
+	pub docs: Vec<String>,
+}
+
+impl ErrorDef {
+	pub fn try_from(item: syn::Item) -> syn::Result<Self> {
+		// First we check that we are parsing an `enum`.
+		let item_enum = if let syn::Item::Enum(item) = item {
+			item
+		} else {
+			return Err(syn::Error::new(item.span(), "Invalid pallet::error, expected item enum"))
+		};
+
+		let mut variants = vec![];
+		for variant in item_enum.variants {
+			// Errors are converted to their index with `as u8`, so they cannot hold any data.
+			if !matches!(variant.fields, syn::Fields::Unit) {
+				let msg = "Invalid pallet::error, variants cannot have fields";
+				return Err(syn::Error::new(variant.fields.span(), msg))
+			}
+
+			let docs = crate::call::parse::get_docs(&variant.attrs);
+			variants.push(ErrorVariantDef { name: variant.ident, docs });
+		}
+
+		Ok(Self { error_enum: item_enum.ident, variants })
+	}
+}
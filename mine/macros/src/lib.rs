@@ -1,6 +1,27 @@
 mod call;
+mod error;
 mod runtime;
 
+/// Expand the callable functions of a pallet.
+///
+/// This generates:
+/// - `enum Call` - an enum with one variant per callable function, holding its arguments. It
+///   implements `support::codec::{Encode, Decode}` whenever all of the arguments do, using the
+///   position of each function as its stable index.
+/// - implements the trait `support::GetWeight` for `Call`. Every callable function must be
+///   annotated with `#[weight(expr)]`, where `expr` evaluates to a `support::Weight` and can refer
+///   to the arguments of the function.
+/// - implements the trait `support::Dispatch` for the pallet, routing each `Call` variant to its
+///   function on behalf of an `origin`. Every callable function takes `origin: T::RuntimeOrigin` as
+///   its first argument after `self`, which it can check with `system::ensure_signed` or
+///   `system::ensure_root`.
+/// - implements `From<Error> for support::DispatchError`, so the pallet's own `enum Error` can be
+///   returned from callable functions with `?` or `.into()`.
+/// - `fn metadata()` on the pallet - which describes every callable function, with its arguments
+///   and doc comments, and every error of the pallet. The `enum Error` must be annotated with
+///   `#[macros::error]`.
+/// - implements the trait `support::Hooks` for the pallet, with hooks which do nothing. A pallet
+///   which implements its own hooks opts out of this with `#[macros::call(hooks)]`.
 #[proc_macro_attribute]
 pub fn call(
 	attr: proc_macro::TokenStream,
@@ -9,22 +30,70 @@ pub fn call(
 	call::call(attr, item)
 }
 
+/// Expand the `Error` enum of a pallet.
+///
+/// This generates `fn metadata()` on the enum, which describes each of its variants, with their
+/// index and doc comments. The variants cannot hold any data.
+#[proc_macro_attribute]
+pub fn error(
+	attr: proc_macro::TokenStream,
+	item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+	error::error(attr, item)
+}
+
 /// Expand the `Runtime` definition.
 ///
 /// This generates function implementations on `Runtime`:
-/// - `fn new()` - which generates a new instance of the runtime, by instantiating all the pallets
-///   included in the runtime.
+/// - `fn from_genesis()` - which generates a new instance of the runtime, by building all the
+///   pallets included in the runtime from a `RuntimeGenesisConfig`, and sealing the resulting state
+///   as the genesis of the chain.
+/// - `fn from_storage()` - which creates an instance of the runtime over a storage already holding
+///   the state of a chain. Every pallet is expected to implement `fn new()` taking the storage.
 /// - `fn execute_block()` - which handles basic logic for executing a block of extrinsics. It does
 ///   basic actions like incrementing the block number and checking the block to be executed has a
-///   valid block number.
+///   valid block number, builds on the last executed block and has a valid extrinsics root and
+///   state root. Every signed extrinsic must have a valid signature and carry the current nonce of
+///   its signer, whose fee is then charged through `support::ChargeTransaction`, which the runtime
+///   must implement. Every unsigned extrinsic must be accepted by its pallet. A rejected block
+///   leaves the runtime untouched. Each extrinsic is dispatched in its own storage transaction,
+///   which is rolled back if the call fails. The `support::Hooks` of every pallet are called, in
+///   declaration order, before and after the extrinsics. A block whose calls weigh more than the
+///   `MAX_BLOCK_WEIGHT` of the system config is rejected.
+/// - `fn state_root()` - the merkle root of the storage which all the pallets share. The system
+///   pallet is expected to hand it out with `fn storage()`.
+/// - `fn initialize_block()`, `fn apply_extrinsic()` and `fn finalize_block()` - the steps of
+///   executing a block, which block authors run one at a time to build a block.
+/// - `fn seal_genesis()` - which turns the current state into the genesis of the chain.
+/// - `fn genesis_header()` - the header of the genesis block of a chain whose genesis state is the
+///   current state.
+/// - `fn metadata()` - which describes every pallet of the runtime, by collecting the metadata of
+///   each pallet.
+/// - `fn collect_events()` - which moves the events deposited by every pallet into the system
+///   pallet. Every pallet is expected to implement `fn take_events()`.
+///
+/// This also generates `struct RuntimeGenesisConfig`, with one field per pallet holding the genesis
+/// configuration of that pallet, as defined by its implementation of `support::BuildGenesis`. It
+/// can be serialized, so the genesis of a chain can be loaded from a file.
 ///
 /// This also generates code needed for dispatching calls to the pallets:
 /// - Note: For simplicity, we assume that the system pallet is not callable.
 /// - `enum RuntimeCall` - an "outer"-enum representing the accumulation of all possible calls to
-///   all pallets. The system pallet is not included.
+///   all pallets. The system pallet is not included. It implements `support::GetWeight` with the
+///   weight of the underlying pallet call, and `support::codec::{Encode, Decode}` using the index
+///   of each pallet.
 /// - implements the trait `support::Dispatch` to dispatch calls to the appropriate pallet. Basic
 ///   logic like incrementing the nonce of the user is included in the generated code. The system
-///   pallet is not included.
+///   pallet is not included. Errors returned by a pallet are tagged with that pallet's index. After
+///   a pallet call succeeds, the calls the pallet queued through `support::DispatchNested` are
+///   dispatched in turn. Every pallet must implement this trait, even if it never queues a call.
+/// - `enum RuntimeEvent` - an "outer"-enum representing the accumulation of all possible events of
+///   all pallets, including the system pallet.
+/// - `enum RuntimeOrigin` - an "outer"-enum representing all possible origins of a call, which for
+///   now are only the `system::RawOrigin`s. Signed extrinsics are dispatched with a `Signed`
+///   origin, and unsigned extrinsics with the `None` origin.
+/// - implements the trait `support::ValidateUnsigned`, routing unsigned calls to their pallet.
+///   Every pallet must implement this trait, even if only to reject all unsigned calls.
 #[proc_macro_attribute]
 pub fn runtime(
 	attr: proc_macro::TokenStream,
@@ -9,63 +9,344 @@ pub fn expand_runtime(def: RuntimeDef) -> proc_macro2::TokenStream {
 	let pallet_names = pallets.iter().map(|(name, _)| name.clone()).collect::<Vec<_>>();
 	// This is a vector of all the pallet types, not including system.
 	let pallet_types = pallets.iter().map(|(_, type_)| type_.clone()).collect::<Vec<_>>();
+	// This is a vector of the index of each pallet in the `Runtime` struct. System is at index 0,
+	// so the other pallets start at 1.
+	let pallet_indices = (1..=pallets.len()).map(|i| i as u8).collect::<Vec<_>>();
+	// Every pallet is offered the weight left in the block in turn, starting with system. Each
+	// pallet but the last leaves the weight it did not use to the pallets after it.
+	let idle_pallets = std::iter::once(quote!(self.system))
+		.chain(pallet_names.iter().map(|name| quote!(self.#name)))
+		.collect::<Vec<_>>();
+	let (last_idle_pallet, idle_pallets) =
+		idle_pallets.split_last().expect("system is always included");
 
 	// This quote block implements functions on the `Runtime` struct.
 	let runtime_impl = quote! {
 		impl #runtime_struct {
-			// Create a new instance of the main Runtime, by creating a new instance of each pallet.
-			fn new() -> Self {
-				Self {
+			// Create a new instance of the main Runtime from its genesis configuration, by building
+			// each pallet from its own part of the configuration. Every pallet keeps its state in
+			// `storage`, which is expected to be empty. The resulting state is sealed as the genesis
+			// of the chain.
+			fn from_genesis(
+				storage: &crate::support::storage::Storage,
+				config: &RuntimeGenesisConfig,
+			) -> Self {
+				let mut runtime = Self {
 					// Since system is not included in the list of pallets, we manually add it here.
-					system: <system::Pallet::<Self>>::new(),
+					system: <system::Pallet<Self> as crate::support::BuildGenesis>::build_genesis(
+						storage,
+						&config.system,
+					),
 					#(
-						#pallet_names: <#pallet_types>::new()
+						#pallet_names: <#pallet_types as crate::support::BuildGenesis>::build_genesis(
+							storage,
+							&config.#pallet_names,
+						)
 					),*
+				};
+				runtime.seal_genesis();
+				runtime
+			}
+
+			// Create a new instance of the main Runtime over a `storage` which already holds the
+			// state of a chain, such as a storage opened from disk. The hashes of the genesis header
+			// and of the last executed block are not part of the state, and must be set on the system
+			// pallet.
+			fn from_storage(storage: &crate::support::storage::Storage) -> Self {
+				Self {
+					system: system::Pallet::new(storage),
+					#( #pallet_names: <#pallet_types>::new(storage) ),*
 				}
 			}
 
+			// The merkle root of the storage shared by every pallet.
+			fn state_root(&self) -> crate::support::H256 {
+				self.system.storage().root()
+			}
+
 			// Execute a block of extrinsics. Increments the block number.
+			//
+			// Block import is all-or-nothing: the header is validated before anything is touched,
+			// and the rest of the block runs inside a storage transaction. If any block-level check
+			// fails, every change made by the block is reverted, including the block number and
+			// the nonces.
 			fn execute_block(&mut self, block: types::Block) -> crate::support::DispatchResult {
-				self.system.inc_block_number();
-				if block.header.block_number != self.system.block_number() {
-					return Err(&"block number does not match what is expected")
+				if block.header.block_number != self.system.next_block_number() {
+					return Err("block number does not match what is expected".into())
 				}
-				for (i, support::Extrinsic { caller, call }) in block.extrinsics.into_iter().enumerate() {
-					self.system.inc_nonce(&caller);
-					let _res = self.dispatch(caller, call).map_err(|e| {
-						eprintln!(
-							"Extrinsic Error\n\tBlock Number: {}\n\tExtrinsic Number: {}\n\tError: {}",
-							block.header.block_number, i, e
-						)
-					});
+				if block.header.parent_hash != self.system.parent_hash() {
+					return Err("block does not build on the last executed block".into())
+				}
+				if block.header.extrinsics_root != block.extrinsics_root() {
+					return Err("extrinsics root does not match the block body".into())
 				}
+				crate::support::with_transaction(&self.system.storage().clone(), self, |runtime| {
+					runtime.apply_block(&block)?;
+					if block.header.state_root != runtime.state_root() {
+						return Err("state root does not match the result of the block".into())
+					}
+					runtime.system.set_parent_hash(block.hash());
+					Ok(())
+				})
+			}
+
+			// Apply the extrinsics of a block whose header has already been validated, surrounded by
+			// the `on_initialize`, `on_idle` and `on_finalize` hooks of every pallet.
+			//
+			// A block whose calls weigh more than `MAX_BLOCK_WEIGHT` in total is invalid, and so is a
+			// block with any extrinsic which `apply_extrinsic` rejects.
+			fn apply_block(&mut self, block: &types::Block) -> crate::support::DispatchResult {
+				let block_weight = block
+					.extrinsics
+					.iter()
+					.map(|extrinsic| crate::support::GetWeight::weight(&extrinsic.call))
+					.fold(0 as crate::support::Weight, crate::support::Weight::saturating_add);
+				if block_weight > <Self as system::Config>::MAX_BLOCK_WEIGHT {
+					return Err("block weight exceeds the maximum block weight".into())
+				}
+
+				self.initialize_block(&block.header);
+				for (i, extrinsic) in block.extrinsics.iter().enumerate() {
+					// A failing call does not make the block invalid.
+					let _ = self.apply_extrinsic(i as u32, extrinsic)?;
+				}
+				self.finalize_block(block_weight);
 				Ok(())
 			}
+
+			// Start the block with `header`: increment the block number, record the author of the
+			// block, and let every pallet run its logic for the start of the block, starting with
+			// system.
+			fn initialize_block(&mut self, header: &types::Header) {
+				self.system.inc_block_number();
+				self.system.set_block_author(header.author.map(Into::into));
+				self.system.set_phase(system::Phase::Initialization);
+				let block_number = self.system.block_number();
+				crate::support::Hooks::on_initialize(&mut self.system, block_number);
+				#( crate::support::Hooks::on_initialize(&mut self.#pallet_names, block_number); )*
+				self.collect_events(system::Phase::Initialization);
+			}
+
+			// Apply `extrinsic` as the extrinsic at `index` in the current block.
+			//
+			// The outer error means the extrinsic is invalid, and so is any block which includes it:
+			// a signed extrinsic with an invalid signature, whose nonce does not match the nonce of its
+			// signer, or whose signer cannot pay its fee, or an unsigned extrinsic which no pallet
+			// accepts. Some changes may already have been made when an extrinsic is found invalid.
+			//
+			// Otherwise the extrinsic is dispatched inside its own storage transaction, and the inner
+			// result is the result of its call. A failing call leaves no trace in any pallet, except
+			// for the nonce increment and the fee of the signer.
+			fn apply_extrinsic(
+				&mut self,
+				index: u32,
+				extrinsic: &types::Extrinsic,
+			) -> Result<crate::support::DispatchResult, crate::support::DispatchError> {
+				let phase = system::Phase::ApplyExtrinsic(index);
+				self.system.set_phase(phase);
+				let call = extrinsic.call.clone();
+				let result = match &extrinsic.signature {
+					Some(crate::support::ExtrinsicSignature { signer, nonce, .. }) => {
+						if !extrinsic.verify(self.system.genesis_hash()) {
+							return Err("extrinsic signature is invalid".into())
+						}
+						// The account of a signed extrinsic is derived from its signer.
+						let caller: <Self as system::Config>::AccountId = (*signer).into();
+						if *nonce != self.system.nonce(&caller) {
+							return Err("extrinsic nonce does not match the caller's nonce".into())
+						}
+						self.system.inc_nonce(&caller);
+						// The fee is withdrawn outside of the transaction of the call, so it is paid
+						// even if the call fails.
+						let len = crate::support::codec::Encode::encode(extrinsic).len();
+						crate::support::ChargeTransaction::charge_transaction(self, &caller, &call, len)?;
+						let origin = system::RawOrigin::Signed(caller).into();
+						crate::support::with_transaction(&self.system.storage().clone(), self, |runtime| {
+							runtime.dispatch(origin, call)
+						})
+					},
+					None => {
+						crate::support::ValidateUnsigned::validate_unsigned(self, &call)?;
+						let origin = system::RawOrigin::None.into();
+						crate::support::with_transaction(&self.system.storage().clone(), self, |runtime| {
+							runtime.dispatch(origin, call)
+						})
+					},
+				};
+				// The events of a failed call were rolled back along with the rest of its changes.
+				self.collect_events(phase);
+				let outcome = match &result {
+					Ok(()) => system::Event::ExtrinsicSuccess,
+					Err(error) => system::Event::ExtrinsicFailed { error: *error },
+				};
+				self.system.deposit_event(phase, RuntimeEvent::system(outcome));
+				Ok(result)
+			}
+
+			// End the current block, whose calls weigh `block_weight` in total: let every pallet run
+			// its logic for the end of the block. The weight left in the block is offered to each
+			// pallet in turn.
+			fn finalize_block(&mut self, block_weight: crate::support::Weight) {
+				self.system.set_phase(system::Phase::Finalization);
+				let block_number = self.system.block_number();
+				let mut remaining_weight =
+					<Self as system::Config>::MAX_BLOCK_WEIGHT.saturating_sub(block_weight);
+				#(
+					remaining_weight = remaining_weight.saturating_sub(
+						crate::support::Hooks::on_idle(&mut #idle_pallets, block_number, remaining_weight),
+					);
+				)*
+				crate::support::Hooks::on_idle(&mut #last_idle_pallet, block_number, remaining_weight);
+				crate::support::Hooks::on_finalize(&mut self.system, block_number);
+				#( crate::support::Hooks::on_finalize(&mut self.#pallet_names, block_number); )*
+				self.collect_events(system::Phase::Finalization);
+			}
+
+			// Move the events deposited by every pallet into the system pallet, which keeps the
+			// events of the whole block.
+			fn collect_events(&mut self, phase: system::Phase) {
+				#(
+					for event in self.#pallet_names.take_events() {
+						self.system.deposit_event(phase, RuntimeEvent::#pallet_names(event));
+					}
+				)*
+			}
+
+			// Describe every pallet of the runtime, with its name and index filled in.
+			pub fn metadata() -> crate::support::metadata::RuntimeMetadata {
+				crate::support::metadata::RuntimeMetadata {
+					pallets: vec![
+						#(
+							crate::support::metadata::PalletMetadata {
+								name: stringify!(#pallet_names),
+								index: #pallet_indices,
+								..<#pallet_types>::metadata()
+							}
+						),*
+					],
+				}
+			}
+
+			// Seal the current state as the genesis state of the chain. The first block must build
+			// on the genesis header, whose hash identifies the chain in the signature of every
+			// extrinsic.
+			fn seal_genesis(&mut self) {
+				let hash = self.genesis_header().hash();
+				self.system.set_genesis_hash(hash);
+				self.system.set_parent_hash(hash);
+			}
+
+			// The genesis header (block 0) of a chain whose genesis state is the current state. It
+			// has no parent and no extrinsics.
+			fn genesis_header(&self) -> types::Header {
+				types::Header {
+					parent_hash: crate::support::H256::default(),
+					block_number: self.system.block_number(),
+					state_root: self.state_root(),
+					extrinsics_root: crate::support::merkle_root(&[]),
+					author: None,
+				}
+			}
 		}
 	};
 
-	// This quote block implements the `RuntimeCall` enum and implements the `Dispatch` trait.
+	// This quote block implements the `RuntimeCall`, `RuntimeEvent` and `RuntimeOrigin` enums and
+	// implements the `Dispatch` trait.
 	let dispatch_impl = quote! {
 		// These are all the calls which are exposed to the world.
 		// Note that it is just an accumulation of the calls exposed by each pallet.
 		//
 		// The parsed function names will be `snake_case`, and that will show up in the enum.
 		#[allow(non_camel_case_types)]
+		#[derive(Debug, Clone)]
 		pub enum RuntimeCall {
 			#( #pallet_names(#pallet_names::Call<#runtime_struct>) ),*
 		}
 
+		// `RuntimeCall` is encoded as the index of the pallet, followed by the pallet call.
+		impl crate::support::codec::Encode for RuntimeCall {
+			fn encode_to(&self, dest: &mut Vec<u8>) {
+				match self {
+					#(
+						RuntimeCall::#pallet_names(call) => {
+							dest.push(#pallet_indices);
+							crate::support::codec::Encode::encode_to(call, dest);
+						},
+					)*
+				}
+			}
+		}
+
+		impl crate::support::codec::Decode for RuntimeCall {
+			fn decode(input: &mut &[u8]) -> Result<Self, crate::support::codec::DecodeError> {
+				match <u8 as crate::support::codec::Decode>::decode(input)? {
+					#(
+						#pallet_indices => Ok(RuntimeCall::#pallet_names(
+							crate::support::codec::Decode::decode(input)?,
+						)),
+					)*
+					_ => Err(crate::support::codec::DecodeError("invalid pallet index")),
+				}
+			}
+		}
+
+		// The weight of a `RuntimeCall` is the weight of the underlying pallet call.
+		impl crate::support::GetWeight for RuntimeCall {
+			fn weight(&self) -> crate::support::Weight {
+				match self {
+					#( RuntimeCall::#pallet_names(call) => crate::support::GetWeight::weight(call) ),*
+				}
+			}
+		}
+
+		// These are all the events which can be deposited in the runtime.
+		// Just like `RuntimeCall`, it is an accumulation of the events of each pallet, but it
+		// also includes the events of the system pallet. Events can be serialized, to be sent to
+		// the clients of a node.
+		#[allow(non_camel_case_types)]
+		#[derive(Debug, Clone, serde::Serialize)]
+		pub enum RuntimeEvent {
+			system(system::Event),
+			#( #pallet_names(#pallet_names::Event<#runtime_struct>) ),*
+		}
+
+		// These are all the origins a call can be dispatched with.
+		// Just like `RuntimeEvent`, it is an accumulation of origins, but for now only the system
+		// pallet defines any.
+		#[allow(non_camel_case_types)]
+		#[derive(Debug, Clone, PartialEq, Eq)]
+		pub enum RuntimeOrigin {
+			system(system::RawOrigin<<#runtime_struct as system::Config>::AccountId>),
+		}
+
+		impl From<system::RawOrigin<<#runtime_struct as system::Config>::AccountId>> for RuntimeOrigin {
+			fn from(origin: system::RawOrigin<<#runtime_struct as system::Config>::AccountId>) -> Self {
+				RuntimeOrigin::system(origin)
+			}
+		}
+
+		// Lets pallets check for system origins with `system::ensure_signed` and friends.
+		impl From<RuntimeOrigin>
+			for Result<system::RawOrigin<<#runtime_struct as system::Config>::AccountId>, RuntimeOrigin>
+		{
+			fn from(origin: RuntimeOrigin) -> Self {
+				match origin {
+					RuntimeOrigin::system(origin) => Ok(origin),
+				}
+			}
+		}
+
 		impl crate::support::Dispatch for #runtime_struct {
-			type Caller = <Runtime as system::Config>::AccountId;
+			type Origin = RuntimeOrigin;
 			type Call = RuntimeCall;
-			// Dispatch a call on behalf of a caller. Increments the caller's nonce.
+			// Dispatch a call on behalf of an origin.
 			//
 			// Dispatch allows us to identify which underlying pallet call we want to execute.
-			// Note that we extract the `caller` from the extrinsic, and use that information
-			// to determine who we are executing the call on behalf of.
+			// Note that the `origin` is derived from the extrinsic, and is how the pallet knows
+			// who it is executing the call on behalf of.
 			fn dispatch(
 				&mut self,
-				caller: Self::Caller,
+				origin: Self::Origin,
 				runtime_call: Self::Call,
 			) -> crate::support::DispatchResult {
 				// This match statement will allow us to correctly route `RuntimeCall`s
@@ -73,18 +354,91 @@ pub fn expand_runtime(def: RuntimeDef) -> proc_macro2::TokenStream {
 				match runtime_call {
 					#(
 						RuntimeCall::#pallet_names(call) => {
-							self.#pallet_names.dispatch(caller, call)?;
+							self.#pallet_names
+								.dispatch(origin, call)
+								.map_err(|e| e.with_pallet_index(#pallet_indices))?;
+							// Then dispatch the calls the pallet queued, if any.
+							let nested_calls =
+								crate::support::DispatchNested::<RuntimeOrigin, RuntimeCall>::take_nested_calls(
+									&mut self.#pallet_names,
+								);
+							for (origin, call) in nested_calls {
+								let result = crate::support::with_transaction(&self.system.storage().clone(), self, |runtime| {
+									runtime.dispatch(origin, call)
+								});
+								crate::support::DispatchNested::<RuntimeOrigin, RuntimeCall>::on_nested_call_result(
+									&mut self.#pallet_names,
+									result,
+								);
+							}
 						}
 					),*
 				}
 				Ok(())
 			}
 		}
+
+		// A transaction over the runtime rolls back the events and nested calls it left in memory,
+		// along with its changes to storage.
+		impl crate::support::Transactional for #runtime_struct {
+			type Checkpoint = system::Checkpoint<Self>;
+
+			fn checkpoint(&mut self) -> Self::Checkpoint {
+				// Events deposited before the transaction are kept whatever its result.
+				self.collect_events(self.system.phase());
+				self.system.checkpoint()
+			}
+
+			fn restore(&mut self, checkpoint: Self::Checkpoint) {
+				#(
+					self.#pallet_names.take_events();
+					crate::support::DispatchNested::<RuntimeOrigin, RuntimeCall>::take_nested_calls(
+						&mut self.#pallet_names,
+					);
+				)*
+				self.system.restore(checkpoint);
+			}
+		}
+	};
+
+	// This quote block creates the `RuntimeGenesisConfig`, which holds the genesis configuration of
+	// every pallet. Any pallet missing from a serialized configuration uses its default.
+	let genesis_config = quote! {
+		#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+		#[serde(default)]
+		pub struct RuntimeGenesisConfig {
+			pub system: <system::Pallet<#runtime_struct> as crate::support::BuildGenesis>::GenesisConfig,
+			#(
+				pub #pallet_names:
+					<#pallet_names::Pallet<#runtime_struct> as crate::support::BuildGenesis>::GenesisConfig
+			),*
+		}
+	};
+
+	// This quote block implements the `ValidateUnsigned` trait, by routing unsigned calls to the
+	// pallet which they belong to.
+	let validate_unsigned_impl = quote! {
+		impl crate::support::ValidateUnsigned for #runtime_struct {
+			type Call = RuntimeCall;
+
+			fn validate_unsigned(&self, runtime_call: &Self::Call) -> crate::support::DispatchResult {
+				match runtime_call {
+					#(
+						RuntimeCall::#pallet_names(call) => {
+							crate::support::ValidateUnsigned::validate_unsigned(&self.#pallet_names, call)
+								.map_err(|e| e.with_pallet_index(#pallet_indices))
+						}
+					),*
+				}
+			}
+		}
 	};
 
 	// We combine and return all the generated code.
 	quote! {
 		#dispatch_impl
+		#validate_unsigned_impl
+		#genesis_config
 		#runtime_impl
 	}
 	.into()
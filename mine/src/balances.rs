@@ -1,31 +1,168 @@
-use num::{CheckedAdd, CheckedSub, Zero};
-use std::collections::BTreeMap;
+use crate::support::{
+    codec::{Decode, Encode},
+    storage::{Storage, StorageMap},
+    ArithmeticError, BuildGenesis, DispatchResult, Weight,
+};
+use crate::system::{ensure_root, ensure_signed};
+use num::{CheckedAdd, CheckedMul, CheckedSub, Zero};
+use serde::{Deserialize, Serialize};
 
 pub trait Config: crate::system::Config {
     /// A denomination type of blockchain's main token
-    type Tokens: Zero + CheckedSub + CheckedAdd + Copy;
+    type Tokens: Zero + CheckedSub + CheckedAdd + CheckedMul + From<u64> + Copy + Encode + Decode;
+
+    /// The fee charged for each unit of weight of a call
+    const FEE_PER_WEIGHT: Self::Tokens;
+
+    /// The fee charged for each byte of an extrinsic
+    const FEE_PER_BYTE: Self::Tokens;
+
+    /// Where the transaction fees go
+    const FEE_DESTINATION: FeeDestination;
+}
+
+/// What happens to the transaction fees withdrawn from the signers of extrinsics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeDestination {
+    /// The fees are destroyed.
+    Burn,
+    /// The fees are given to the author of the block. The fees of a block without an author are
+    /// destroyed.
+    BlockAuthor,
+}
+
+/// Errors which can be returned by the balances pallet.
+#[macros::error]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The caller does not have enough funds for the requested transfer.
+    InsufficientFunds,
+}
+
+/// Events deposited by the balances pallet.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(bound(serialize = "T::AccountId: Serialize, T::Tokens: Serialize"))]
+pub enum Event<T: Config> {
+    /// Some tokens were transferred from one account to another.
+    Transfer {
+        from: T::AccountId,
+        to: T::AccountId,
+        amount: T::Tokens,
+    },
+    /// The balance of an account was set by root.
+    BalanceSet {
+        who: T::AccountId,
+        amount: T::Tokens,
+    },
+    /// A transaction fee was withdrawn from the signer of an extrinsic.
+    FeePaid { who: T::AccountId, fee: T::Tokens },
+}
+
+/// The balances held by accounts at genesis.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "T::AccountId: Serialize, T::Tokens: Serialize",
+    deserialize = "T::AccountId: Deserialize<'de>, T::Tokens: Deserialize<'de>"
+))]
+pub struct GenesisConfig<T: Config> {
+    pub balances: Vec<(T::AccountId, T::Tokens)>,
+}
+
+impl<T: Config> Default for GenesisConfig<T> {
+    fn default() -> Self {
+        GenesisConfig {
+            balances: Vec::new(),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Pallet<T: Config> {
-    balances: BTreeMap<T::AccountId, T::Tokens>,
+    balances: StorageMap<T::AccountId, T::Tokens>,
+    /// Events deposited since the runtime last collected them
+    events: Vec<Event<T>>,
 }
 
 impl<T: Config> Pallet<T> {
-    pub fn new() -> Self {
+    pub fn new(storage: &Storage) -> Self {
         Pallet {
-            balances: BTreeMap::new(),
+            balances: StorageMap::new(storage, "Balances", "Balances"),
+            events: Vec::new(),
         }
     }
 
+    /// Deposit an event, to be collected by the runtime
+    pub fn deposit_event(&mut self, event: Event<T>) {
+        self.events.push(event);
+    }
+
+    /// Take all the events deposited since the last call
+    pub fn take_events(&mut self) -> Vec<Event<T>> {
+        std::mem::take(&mut self.events)
+    }
+
     /// Set balance for given account `who`
     pub fn set_balance(&mut self, who: &T::AccountId, value: T::Tokens) {
-        self.balances.insert(who.clone(), value);
+        self.balances.insert(who, &value);
     }
 
     /// Get balance of account `who` (defaults to 0)
     pub fn get_balance(&self, who: &T::AccountId) -> T::Tokens {
-        *self.balances.get(who).unwrap_or(&T::Tokens::zero())
+        self.balances.get(who).unwrap_or(T::Tokens::zero())
+    }
+
+    /// The fee of an extrinsic whose call has `weight`, and which is `len` bytes long.
+    /// Returns `None` if the fee overflows.
+    pub fn compute_fee(weight: Weight, len: usize) -> Option<T::Tokens> {
+        let weight_fee = T::Tokens::from(weight).checked_mul(&T::FEE_PER_WEIGHT)?;
+        let len_fee = T::Tokens::from(len as u64).checked_mul(&T::FEE_PER_BYTE)?;
+        weight_fee.checked_add(&len_fee)
+    }
+
+    /// Withdraw the fee of an extrinsic from `who`, and send it to the `FEE_DESTINATION`, where
+    /// `author` is the author of the current block. Nothing changes if `who` cannot pay, or if the
+    /// destination cannot hold the fee.
+    pub fn charge_fee(
+        &mut self,
+        who: &T::AccountId,
+        author: Option<&T::AccountId>,
+        weight: Weight,
+        len: usize,
+    ) -> DispatchResult {
+        let fee = Self::compute_fee(weight, len).ok_or(ArithmeticError::Overflow)?;
+        let new_balance = self
+            .get_balance(who)
+            .checked_sub(&fee)
+            .ok_or(Error::InsufficientFunds)?;
+        let dest = match T::FEE_DESTINATION {
+            FeeDestination::Burn => None,
+            FeeDestination::BlockAuthor => author,
+        };
+        let credit = match dest {
+            Some(dest) => {
+                // The signer may be the author, in which case the fee comes back to it.
+                let balance = if dest == who {
+                    new_balance
+                } else {
+                    self.get_balance(dest)
+                };
+                let new_dest_balance =
+                    balance.checked_add(&fee).ok_or(ArithmeticError::Overflow)?;
+                Some((dest, new_dest_balance))
+            }
+            None => None,
+        };
+
+        self.set_balance(who, new_balance);
+        if let Some((dest, new_dest_balance)) = credit {
+            self.set_balance(dest, new_dest_balance);
+        }
+
+        self.deposit_event(Event::FeePaid {
+            who: who.clone(),
+            fee,
+        });
+        Ok(())
     }
 }
 
@@ -33,46 +170,124 @@ impl<T: Config> Pallet<T> {
 impl<T: Config> Pallet<T> {
     /// Move frunds from one account to another, only if
     /// requested transfer is valid
+    #[weight(100)]
     pub fn transfer(
         &mut self,
-        caller: T::AccountId,
+        origin: T::RuntimeOrigin,
         to: T::AccountId,
         amount: T::Tokens,
-    ) -> crate::support::DispatchResult {
+    ) -> DispatchResult {
+        let caller = ensure_signed(origin)?;
         let from_balance = self.get_balance(&caller);
-        let to_balance = self.get_balance(&to);
 
         // safely calculate new balances
         let new_from_balance = from_balance
             .checked_sub(&amount)
-            .ok_or("Not enough funds.")?;
-        let new_to_balance = to_balance.checked_add(&amount).ok_or("Fund overflow.")?;
+            .ok_or(Error::InsufficientFunds)?;
+
+        // a transfer to the caller itself leaves its balance as it is
+        if caller != to {
+            let new_to_balance = self
+                .get_balance(&to)
+                .checked_add(&amount)
+                .ok_or(ArithmeticError::Overflow)?;
+
+            // update balances if valid
+            self.set_balance(&caller, new_from_balance);
+            self.set_balance(&to, new_to_balance);
+        }
 
-        // update balances if valid
-        self.set_balance(&caller, new_from_balance);
-        self.set_balance(&to, new_to_balance);
+        self.deposit_event(Event::Transfer {
+            from: caller,
+            to,
+            amount,
+        });
 
         Ok(())
     }
+
+    /// Set the balance of any account `who` to `amount`.
+    /// Only root can do this.
+    #[weight(50)]
+    pub fn force_set_balance(
+        &mut self,
+        origin: T::RuntimeOrigin,
+        who: T::AccountId,
+        amount: T::Tokens,
+    ) -> DispatchResult {
+        ensure_root(origin)?;
+        self.set_balance(&who, amount);
+        self.deposit_event(Event::BalanceSet { who, amount });
+        Ok(())
+    }
+}
+
+impl<T: Config> BuildGenesis for Pallet<T> {
+    type GenesisConfig = GenesisConfig<T>;
+
+    fn build_genesis(storage: &Storage, config: &GenesisConfig<T>) -> Self {
+        let mut pallet = Self::new(storage);
+        for (who, balance) in &config.balances {
+            pallet.set_balance(who, *balance);
+        }
+        pallet
+    }
+}
+
+// This pallet never dispatches calls of other pallets.
+impl<T: Config> crate::support::DispatchNested<T::RuntimeOrigin, T::RuntimeCall> for Pallet<T> {}
+
+// This pallet does not accept any unsigned calls.
+impl<T: Config> crate::support::ValidateUnsigned for Pallet<T> {
+    type Call = Call<T>;
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::support::DispatchError;
+    use crate::system::RawOrigin;
 
+    #[derive(Debug, PartialEq)]
     struct TestConfig;
     impl crate::system::Config for TestConfig {
         type AccountId = String;
         type BlockNumber = u32;
         type Nonce = u32;
+        type RuntimeEvent = ();
+        type RuntimeCall = ();
+        type RuntimeOrigin = crate::system::RawOrigin<String>;
+        const MAX_BLOCK_WEIGHT: u64 = 1_000;
     }
     impl Config for TestConfig {
         type Tokens = u128;
+        const FEE_PER_WEIGHT: u128 = 2;
+        const FEE_PER_BYTE: u128 = 1;
+        const FEE_DESTINATION: FeeDestination = FeeDestination::Burn;
+    }
+
+    /// Like `TestConfig`, but the fees go to the block author.
+    #[derive(Debug, PartialEq)]
+    struct AuthorConfig;
+    impl crate::system::Config for AuthorConfig {
+        type AccountId = String;
+        type BlockNumber = u32;
+        type Nonce = u32;
+        type RuntimeEvent = ();
+        type RuntimeCall = ();
+        type RuntimeOrigin = crate::system::RawOrigin<String>;
+        const MAX_BLOCK_WEIGHT: u64 = 1_000;
+    }
+    impl Config for AuthorConfig {
+        type Tokens = u128;
+        const FEE_PER_WEIGHT: u128 = 2;
+        const FEE_PER_BYTE: u128 = 1;
+        const FEE_DESTINATION: FeeDestination = FeeDestination::BlockAuthor;
     }
 
     #[test]
     fn init_balances() {
-        let mut pallet = Pallet::<TestConfig>::new();
+        let mut pallet = Pallet::<TestConfig>::new(&Storage::default());
 
         assert_eq!(pallet.get_balance(&"Alice".to_string()), 0);
         pallet.set_balance(&"Alice".to_string(), 100);
@@ -80,26 +295,211 @@ mod tests {
         assert_eq!(pallet.get_balance(&"Bob".to_string()), 0);
     }
 
+    #[test]
+    fn state_root() {
+        let storage = Storage::default();
+        let mut pallet = Pallet::<TestConfig>::new(&storage);
+        let empty_root = storage.root();
+
+        pallet.set_balance(&"Alice".to_string(), 100);
+        let root = storage.root();
+        assert_ne!(root, empty_root);
+
+        pallet.set_balance(&"Alice".to_string(), 99);
+        assert_ne!(storage.root(), root);
+        pallet.set_balance(&"Alice".to_string(), 100);
+        assert_eq!(storage.root(), root);
+    }
+
+    #[test]
+    fn build_genesis() {
+        let pallet = Pallet::<TestConfig>::build_genesis(
+            &Storage::default(),
+            &GenesisConfig {
+                balances: vec![("Alice".to_string(), 100), ("Bob".to_string(), 50)],
+            },
+        );
+        assert_eq!(pallet.get_balance(&"Alice".to_string()), 100);
+        assert_eq!(pallet.get_balance(&"Bob".to_string()), 50);
+        assert_eq!(pallet.get_balance(&"Charlie".to_string()), 0);
+    }
+
     #[test]
     fn transfer_balance() {
-        let mut balances = Pallet::<TestConfig>::new();
+        let mut balances = Pallet::<TestConfig>::new(&Storage::default());
         assert_eq!(
-            balances.transfer("alice".to_string(), "bob".to_string(), 22),
-            Err("Not enough funds.")
+            balances.transfer(
+                RawOrigin::Signed("alice".to_string()),
+                "bob".to_string(),
+                22
+            ),
+            Err(Error::InsufficientFunds.into())
         );
 
         // balances.set_balance(&"alice".to_string(), 35);
 
         balances.set_balance(&"alice".to_string(), 100);
         assert_eq!(
-            balances.transfer("alice".to_string(), "bob".to_string(), 22),
+            balances.transfer(
+                RawOrigin::Signed("alice".to_string()),
+                "bob".to_string(),
+                22
+            ),
             Ok(())
         );
         assert_eq!(balances.get_balance(&"alice".to_string()), 78);
         assert_eq!(balances.get_balance(&"bob".to_string()), 22);
         assert_eq!(
-            balances.transfer("alice".to_string(), "bob".to_string(), 80),
-            Err("Not enough funds.")
+            balances.take_events(),
+            vec![Event::Transfer {
+                from: "alice".to_string(),
+                to: "bob".to_string(),
+                amount: 22,
+            }]
+        );
+        assert_eq!(
+            balances.transfer(
+                RawOrigin::Signed("alice".to_string()),
+                "bob".to_string(),
+                80
+            ),
+            Err(Error::InsufficientFunds.into())
+        );
+
+        balances.set_balance(&"bob".to_string(), u128::MAX);
+        assert_eq!(
+            balances.transfer(RawOrigin::Signed("alice".to_string()), "bob".to_string(), 1),
+            Err(ArithmeticError::Overflow.into())
+        );
+        assert_eq!(balances.take_events(), vec![]);
+    }
+
+    #[test]
+    fn transfer_to_self() {
+        let mut balances = Pallet::<TestConfig>::new(&Storage::default());
+        balances.set_balance(&"alice".to_string(), 100);
+        assert_eq!(
+            balances.transfer(
+                RawOrigin::Signed("alice".to_string()),
+                "alice".to_string(),
+                101
+            ),
+            Err(Error::InsufficientFunds.into())
+        );
+        assert_eq!(
+            balances.transfer(
+                RawOrigin::Signed("alice".to_string()),
+                "alice".to_string(),
+                60
+            ),
+            Ok(())
+        );
+        assert_eq!(balances.get_balance(&"alice".to_string()), 100);
+        assert_eq!(
+            balances.take_events(),
+            vec![Event::Transfer {
+                from: "alice".to_string(),
+                to: "alice".to_string(),
+                amount: 60,
+            }]
+        );
+    }
+
+    #[test]
+    fn charge_fee() {
+        let mut balances = Pallet::<TestConfig>::new(&Storage::default());
+        assert_eq!(Pallet::<TestConfig>::compute_fee(10, 5), Some(25));
+
+        balances.set_balance(&"alice".to_string(), 30);
+        assert_eq!(
+            balances.charge_fee(&"alice".to_string(), None, 10, 5),
+            Ok(())
+        );
+        assert_eq!(balances.get_balance(&"alice".to_string()), 5);
+        assert_eq!(
+            balances.take_events(),
+            vec![Event::FeePaid {
+                who: "alice".to_string(),
+                fee: 25,
+            }]
+        );
+
+        assert_eq!(
+            balances.charge_fee(&"alice".to_string(), None, 10, 5),
+            Err(Error::InsufficientFunds.into())
+        );
+        assert_eq!(balances.get_balance(&"alice".to_string()), 5);
+        assert_eq!(balances.take_events(), vec![]);
+
+        // The fee is burned, even when the block has an author.
+        balances.set_balance(&"alice".to_string(), 30);
+        let author = "bob".to_string();
+        assert_eq!(
+            balances.charge_fee(&"alice".to_string(), Some(&author), 10, 5),
+            Ok(())
+        );
+        assert_eq!(balances.get_balance(&author), 0);
+    }
+
+    #[test]
+    fn fee_is_paid_to_block_author() {
+        let mut balances = Pallet::<AuthorConfig>::new(&Storage::default());
+        let (alice, bob) = ("alice".to_string(), "bob".to_string());
+        balances.set_balance(&alice, 100);
+
+        assert_eq!(balances.charge_fee(&alice, Some(&bob), 10, 5), Ok(()));
+        assert_eq!(balances.get_balance(&alice), 75);
+        assert_eq!(balances.get_balance(&bob), 25);
+        // Without an author, the fee is burned.
+        assert_eq!(balances.charge_fee(&alice, None, 10, 5), Ok(()));
+        assert_eq!(balances.get_balance(&alice), 50);
+        assert_eq!(balances.get_balance(&bob), 25);
+        // An author paying its own fee keeps it.
+        assert_eq!(balances.charge_fee(&bob, Some(&bob), 10, 5), Ok(()));
+        assert_eq!(balances.get_balance(&bob), 25);
+        balances.take_events();
+
+        // An author which cannot hold the fee makes the charge fail, and nothing is withdrawn.
+        balances.set_balance(&bob, u128::MAX);
+        assert_eq!(
+            balances.charge_fee(&alice, Some(&bob), 10, 5),
+            Err(ArithmeticError::Overflow.into())
+        );
+        assert_eq!(balances.get_balance(&alice), 50);
+        assert_eq!(balances.get_balance(&bob), u128::MAX);
+        assert_eq!(balances.take_events(), vec![]);
+    }
+
+    #[test]
+    fn force_set_balance() {
+        let mut balances = Pallet::<TestConfig>::new(&Storage::default());
+        assert_eq!(
+            balances.force_set_balance(
+                RawOrigin::Signed("alice".to_string()),
+                "alice".to_string(),
+                100
+            ),
+            Err(DispatchError::BadOrigin)
+        );
+        assert_eq!(balances.get_balance(&"alice".to_string()), 0);
+
+        assert_eq!(
+            balances.force_set_balance(RawOrigin::Root, "alice".to_string(), 100),
+            Ok(())
+        );
+        assert_eq!(balances.get_balance(&"alice".to_string()), 100);
+        assert_eq!(
+            balances.take_events(),
+            vec![Event::BalanceSet {
+                who: "alice".to_string(),
+                amount: 100,
+            }]
+        );
+
+        // Root has no account to transfer from.
+        assert_eq!(
+            balances.transfer(RawOrigin::Root, "bob".to_string(), 1),
+            Err(DispatchError::BadOrigin)
         );
     }
 }
@@ -0,0 +1,180 @@
+use crate::support::{
+    codec::Encode, crypto::Public, with_transaction, DispatchError, DispatchResult, GetWeight,
+    Weight,
+};
+use crate::{system, types, Runtime};
+use std::mem;
+
+/// The maximum encoded size of the extrinsics of a block, by default.
+pub const DEFAULT_MAX_BLOCK_SIZE: usize = 64 * 1024;
+
+/// The reasons an extrinsic is not added to a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The extrinsic cannot be included in a block on top of the current state.
+    Invalid(DispatchError),
+    /// The extrinsic does not fit in the weight or the size left in the block.
+    ExhaustsResources,
+}
+
+/// Builds a block on top of the state of a runtime, one extrinsic at a time.
+///
+/// The extrinsics are applied to a copy of the runtime as they are pushed, so the author knows the
+/// result of each of them, and invalid extrinsics are left out of the block. The copy shares the
+/// storage of the runtime, so its changes are made in a storage transaction which is rolled back
+/// when the builder is dropped. Until then, the storage holds the state of the block being built,
+/// and no other transaction may be started or ended on it.
+pub struct BlockBuilder {
+    runtime: Runtime,
+    header: types::Header,
+    extrinsics: Vec<types::Extrinsic>,
+    weight: Weight,
+    size: usize,
+    max_size: usize,
+}
+
+impl BlockBuilder {
+    /// Start a block on top of the last block executed by `parent`, authored by `author`, whose
+    /// extrinsics take at most `max_size` bytes once encoded.
+    pub fn new(parent: &Runtime, author: Option<Public>, max_size: usize) -> Self {
+        let mut runtime = parent.clone();
+        runtime.system.storage().start_transaction();
+        let header = types::Header {
+            parent_hash: runtime.system.parent_hash(),
+            block_number: runtime.system.next_block_number(),
+            state_root: Default::default(),
+            extrinsics_root: Default::default(),
+            author,
+        };
+        runtime.initialize_block(&header);
+        BlockBuilder {
+            runtime,
+            header,
+            extrinsics: Vec::new(),
+            weight: 0,
+            size: 0,
+            max_size,
+        }
+    }
+
+    /// Apply `extrinsic` and add it to the block. Returns the result of its call, which may fail
+    /// without preventing the extrinsic from being included.
+    ///
+    /// An extrinsic which is invalid, or does not fit in the block, is not added, and leaves the
+    /// block as it was.
+    pub fn push(&mut self, extrinsic: types::Extrinsic) -> Result<DispatchResult, Error> {
+        let weight = self.weight.saturating_add(extrinsic.call.weight());
+        let size = self.size + extrinsic.encode().len();
+        if weight > <Runtime as system::Config>::MAX_BLOCK_WEIGHT || size > self.max_size {
+            return Err(Error::ExhaustsResources);
+        }
+
+        let index = self.extrinsics.len() as u32;
+        let storage = self.runtime.system.storage().clone();
+        let result = with_transaction(&storage, &mut self.runtime, |runtime| {
+            runtime.apply_extrinsic(index, &extrinsic)
+        })
+        .map_err(Error::Invalid)?;
+
+        self.weight = weight;
+        self.size = size;
+        self.extrinsics.push(extrinsic);
+        Ok(result)
+    }
+
+    /// Finalize the block, and seal its header with the extrinsics it holds and the state it
+    /// produces.
+    pub fn build(mut self) -> types::Block {
+        self.runtime.finalize_block(self.weight);
+        let mut block = types::Block {
+            header: self.header.clone(),
+            extrinsics: mem::take(&mut self.extrinsics),
+        };
+        block.header.extrinsics_root = block.extrinsics_root();
+        block.header.state_root = self.runtime.state_root();
+        block
+    }
+}
+
+impl Drop for BlockBuilder {
+    fn drop(&mut self) {
+        self.runtime.system.storage().rollback_transaction();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::support::{crypto::Pair, storage::Storage};
+    use crate::tests::{account, test_genesis, transfer};
+    use crate::{proof_of_existance, sudo, RuntimeCall};
+
+    #[test]
+    fn extrinsics_are_applied_one_at_a_time() {
+        let mut runtime = Runtime::from_genesis(&Storage::default(), &test_genesis());
+        let state_root = runtime.state_root();
+        let alice = Pair::from_name("Alice");
+        let signed = |call, nonce| {
+            types::Extrinsic::new_signed(call, nonce, &alice, runtime.system.genesis_hash())
+        };
+
+        let first = transfer(&runtime, "Bob", 30);
+        let mut builder = BlockBuilder::new(&runtime, None, DEFAULT_MAX_BLOCK_SIZE);
+        assert_eq!(builder.push(first.clone()), Ok(Ok(())));
+        // The nonce of Alice was used by the transfer.
+        assert_eq!(
+            builder.push(first),
+            Err(Error::Invalid(DispatchError::Other(
+                "extrinsic nonce does not match the caller's nonce"
+            )))
+        );
+        // A failing call is still included.
+        let revoke = RuntimeCall::proof_of_existance(proof_of_existance::Call::revoke_claim {
+            claim: "missing".to_string(),
+        });
+        assert!(matches!(builder.push(signed(revoke, 1)), Ok(Err(_))));
+        let set_key = RuntimeCall::sudo(sudo::Call::set_key {
+            new: account("Bob"),
+        });
+        assert_eq!(builder.push(signed(set_key, 2)), Ok(Ok(())));
+        let block = builder.build();
+        assert_eq!(block.extrinsics.len(), 3);
+
+        // Building the block leaves the runtime untouched.
+        assert_eq!(runtime.state_root(), state_root);
+        assert_eq!(runtime.sudo.key(), Some(account("Alice")));
+
+        assert_eq!(block.header.block_number, 1);
+        assert_eq!(runtime.execute_block(block), Ok(()));
+        assert_eq!(runtime.balances.get_balance(&account("Bob")), 30);
+        assert_eq!(runtime.sudo.key(), Some(account("Bob")));
+    }
+
+    #[test]
+    fn block_is_full_at_size_limit() {
+        let mut runtime = Runtime::from_genesis(&Storage::default(), &test_genesis());
+        let alice = Pair::from_name("Alice");
+        let transfer_with_nonce = |nonce| {
+            types::Extrinsic::new_signed(
+                transfer(&runtime, "Bob", 1).call,
+                nonce,
+                &alice,
+                runtime.system.genesis_hash(),
+            )
+        };
+        let max_size = transfer_with_nonce(0).encode().len() * 2;
+
+        let mut builder = BlockBuilder::new(&runtime, None, max_size);
+        assert_eq!(builder.push(transfer_with_nonce(0)), Ok(Ok(())));
+        assert_eq!(builder.push(transfer_with_nonce(1)), Ok(Ok(())));
+        assert_eq!(
+            builder.push(transfer_with_nonce(2)),
+            Err(Error::ExhaustsResources)
+        );
+        let block = builder.build();
+
+        assert_eq!(block.extrinsics.len(), 2);
+        assert_eq!(runtime.execute_block(block), Ok(()));
+        assert_eq!(runtime.balances.get_balance(&account("Bob")), 2);
+    }
+}
@@ -0,0 +1,434 @@
+use crate::support::{
+    codec::{Decode, Encode},
+    db::Log,
+    storage::Changes,
+    H256,
+};
+use crate::types;
+use serde::Deserialize;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    fmt, io,
+    path::Path,
+    rc::Rc,
+};
+
+/// The reasons the chain store can fail.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing the blocks failed.
+    Io(io::Error),
+    /// The blocks on disk are not a valid chain for the given genesis.
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Corrupt(reason) => write!(f, "corrupt chain data: {}", reason),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// A way to refer to a block of the chain. In JSON, this is either a number or a hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum BlockId {
+    /// The block with this number.
+    Number(types::BlockNumber),
+    /// The block with this hash.
+    Hash(H256),
+}
+
+/// A rule to pick the best chain out of the known blocks.
+pub trait ForkChoice {
+    /// Whether the chain ending at `candidate` is better than the chain ending at `best`.
+    ///
+    /// This must be a strict total order of the blocks, so that nodes which know the same blocks
+    /// pick the same best block, whatever the order they imported the blocks in.
+    fn is_better(
+        &self,
+        chain: &ChainStore,
+        candidate: &types::Header,
+        best: &types::Header,
+    ) -> bool;
+}
+
+/// The longest chain is the best. Of two chains of the same length, the best is the one whose last
+/// block has the lowest hash.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LongestChain;
+
+impl ForkChoice for LongestChain {
+    fn is_better(&self, _: &ChainStore, candidate: &types::Header, best: &types::Header) -> bool {
+        (candidate.block_number, Reverse(candidate.hash()))
+            > (best.block_number, Reverse(best.hash()))
+    }
+}
+
+/// An imported block, along with the changes which revert the state after the block to the state
+/// after its parent.
+struct StoredBlock {
+    block: types::Block,
+    undo: Changes,
+}
+
+/// The blocks of a chain, from its genesis to the end of every branch.
+///
+/// Every imported block is kept on disk in an append-only log, and indexed in memory by hash when
+/// the store is opened. The blocks of the best chain, as picked by the fork-choice rule, are also
+/// indexed by number. The genesis block is not written, since it can be built again from the
+/// genesis configuration.
+pub struct ChainStore {
+    /// The log the blocks are written to, unless the store was opened read-only.
+    log: Option<Log>,
+    blocks: HashMap<H256, StoredBlock>,
+    /// The hash of the block at each number of the best chain.
+    numbers: BTreeMap<types::BlockNumber, H256>,
+    best: H256,
+    fork_choice: Rc<dyn ForkChoice>,
+}
+
+impl ChainStore {
+    /// Open the store kept at `path`, or start a new one, for the chain starting at `genesis`.
+    pub fn open(
+        path: &Path,
+        genesis: types::Header,
+        fork_choice: Rc<dyn ForkChoice>,
+    ) -> Result<ChainStore, Error> {
+        let (log, records) = Log::open(path)?;
+        ChainStore::from_records(records, Some(log), genesis, fork_choice)
+    }
+
+    /// Read the store kept at `path`, which must exist, without ever writing to it. The blocks
+    /// imported afterwards are only held in memory.
+    pub fn open_read_only(
+        path: &Path,
+        genesis: types::Header,
+        fork_choice: Rc<dyn ForkChoice>,
+    ) -> Result<ChainStore, Error> {
+        ChainStore::from_records(Log::read(path)?, None, genesis, fork_choice)
+    }
+
+    /// The store of the blocks in the log `records`, written to `log` if any.
+    fn from_records(
+        records: Vec<Vec<u8>>,
+        log: Option<Log>,
+        genesis: types::Header,
+        fork_choice: Rc<dyn ForkChoice>,
+    ) -> Result<ChainStore, Error> {
+        let genesis = types::Block {
+            header: genesis,
+            extrinsics: Vec::new(),
+        };
+        let genesis_hash = genesis.hash();
+        let mut store = ChainStore {
+            log,
+            blocks: HashMap::new(),
+            numbers: BTreeMap::from([(genesis.header.block_number, genesis_hash)]),
+            best: genesis_hash,
+            fork_choice,
+        };
+        store.blocks.insert(
+            genesis_hash,
+            StoredBlock {
+                block: genesis,
+                undo: Changes::new(),
+            },
+        );
+
+        for (i, record) in records.iter().enumerate() {
+            let (block, undo) = <(types::Block, Changes)>::decode_all(record)
+                .map_err(|_| Error::Corrupt("invalid block encoding"))?;
+            if !store.contains(&block.header.parent_hash) {
+                return Err(Error::Corrupt(if i == 0 {
+                    "the chain was started from another genesis"
+                } else {
+                    "a block builds on an unknown block"
+                }));
+            }
+            store.insert(block, undo);
+        }
+        Ok(store)
+    }
+
+    /// Write `block` to disk, along with the changes which revert its state to the state of its
+    /// parent. The block becomes the best block if the fork-choice rule prefers it.
+    ///
+    /// The parent of the block must be known, and the block must not.
+    pub fn import(&mut self, block: types::Block, undo: Changes) -> Result<(), Error> {
+        assert!(
+            self.contains(&block.header.parent_hash),
+            "blocks are imported after their parent"
+        );
+        assert!(!self.contains(&block.hash()), "blocks are imported once");
+        if let Some(log) = &mut self.log {
+            log.append(&(&block, &undo).encode())?;
+        }
+        self.insert(block, undo);
+        Ok(())
+    }
+
+    fn insert(&mut self, block: types::Block, undo: Changes) {
+        let hash = block.hash();
+        let best = &self.blocks[&self.best].block.header;
+        let is_best = self.fork_choice.is_better(self, &block.header, best);
+        self.blocks.insert(hash, StoredBlock { block, undo });
+        if is_best {
+            self.set_best(hash);
+        }
+    }
+
+    /// Make `hash` the best block, and index the blocks of its chain by number.
+    fn set_best(&mut self, hash: H256) {
+        let number = self.blocks[&hash].block.header.block_number;
+        self.numbers.split_off(&(number + 1));
+        let mut current = hash;
+        while self
+            .numbers
+            .get(&self.blocks[&current].block.header.block_number)
+            != Some(&current)
+        {
+            let header = &self.blocks[&current].block.header;
+            self.numbers.insert(header.block_number, current);
+            current = header.parent_hash;
+        }
+        self.best = hash;
+    }
+
+    /// Whether the block with `hash` is known.
+    pub fn contains(&self, hash: &H256) -> bool {
+        self.blocks.contains_key(hash)
+    }
+
+    /// The hash of the genesis block.
+    pub fn genesis_hash(&self) -> H256 {
+        let (_, hash) = self
+            .numbers
+            .first_key_value()
+            .expect("the genesis is always known");
+        *hash
+    }
+
+    /// The number of the genesis block.
+    pub fn genesis_number(&self) -> types::BlockNumber {
+        let (number, _) = self
+            .numbers
+            .first_key_value()
+            .expect("the genesis is always known");
+        *number
+    }
+
+    /// The hash of the best block, which the next block builds on.
+    pub fn best_hash(&self) -> H256 {
+        self.best
+    }
+
+    /// The number of the best block.
+    pub fn best_number(&self) -> types::BlockNumber {
+        self.blocks[&self.best].block.header.block_number
+    }
+
+    /// The hash of the block with the given number in the best chain.
+    pub fn hash(&self, number: types::BlockNumber) -> Option<H256> {
+        self.numbers.get(&number).copied()
+    }
+
+    /// The block `id`, with all its extrinsics. Blocks outside of the best chain can only be found
+    /// by hash.
+    pub fn block(&self, id: BlockId) -> Option<&types::Block> {
+        let hash = match id {
+            BlockId::Number(number) => self.hash(number)?,
+            BlockId::Hash(hash) => hash,
+        };
+        self.blocks.get(&hash).map(|stored| &stored.block)
+    }
+
+    /// The header of block `id`.
+    pub fn header(&self, id: BlockId) -> Option<&types::Header> {
+        self.block(id).map(|block| &block.header)
+    }
+
+    /// The extrinsic at `index` in block `id`.
+    pub fn extrinsic(&self, id: BlockId, index: usize) -> Option<&types::Extrinsic> {
+        self.block(id)?.extrinsics.get(index)
+    }
+
+    /// The changes which revert the state after the block with `hash` to the state after its
+    /// parent.
+    pub fn undo(&self, hash: &H256) -> Option<&Changes> {
+        self.blocks.get(hash).map(|stored| &stored.undo)
+    }
+
+    /// Find a block with the given number, whose state has the given root, preferring the blocks
+    /// of the best chain.
+    pub fn find(&self, number: types::BlockNumber, state_root: H256) -> Option<H256> {
+        let matches = |hash: &H256| {
+            let header = &self.blocks[hash].block.header;
+            header.block_number == number && header.state_root == state_root
+        };
+        self.hash(number)
+            .filter(matches)
+            .or_else(|| self.blocks.keys().copied().find(matches))
+    }
+
+    /// The way from the block `from` to the block `to`: the blocks to revert, starting with `from`,
+    /// down to their latest common ancestor, and then the blocks to execute up to `to`. Neither
+    /// includes the common ancestor.
+    pub fn route(&self, from: H256, to: H256) -> (Vec<H256>, Vec<H256>) {
+        let header = |hash: &H256| &self.blocks[hash].block.header;
+        let (mut retracted, mut enacted) = (Vec::new(), Vec::new());
+        let (mut from, mut to) = (from, to);
+        while from != to {
+            if header(&from).block_number >= header(&to).block_number {
+                retracted.push(from);
+                from = header(&from).parent_hash;
+            } else {
+                enacted.push(to);
+                to = header(&to).parent_hash;
+            }
+        }
+        enacted.reverse();
+        (retracted, enacted)
+    }
+}
+
+#[cfg(test)]
+impl ChainStore {
+    /// Make every later write of a block fail, or work again.
+    pub fn set_writable(&mut self, writable: bool) {
+        self.log.as_mut().unwrap().set_writable(writable);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::support::{db::test_dir, hash_of, storage::Storage};
+    use crate::tests::{test_genesis, transfer_block};
+    use crate::Runtime;
+    use std::fs;
+
+    #[test]
+    fn blocks_are_indexed_by_number_and_hash() {
+        let dir = test_dir("chain-index");
+        let path = dir.join("blocks.log");
+        let mut runtime = Runtime::from_genesis(&Storage::default(), &test_genesis());
+        let genesis = runtime.genesis_header();
+
+        let mut store = ChainStore::open(&path, genesis.clone(), Rc::new(LongestChain)).unwrap();
+        assert_eq!(store.best_hash(), genesis.hash());
+        assert_eq!(
+            store.header(BlockId::Number(0)).unwrap().hash(),
+            genesis.hash()
+        );
+        let mut blocks = Vec::new();
+        for _ in 0..3 {
+            let block = transfer_block(&runtime, "Bob", 30);
+            runtime.execute_block(block.clone()).unwrap();
+            store.import(block.clone(), Changes::new()).unwrap();
+            blocks.push(block);
+        }
+        drop(store);
+
+        let store = ChainStore::open(&path, genesis.clone(), Rc::new(LongestChain)).unwrap();
+        assert_eq!(store.genesis_hash(), genesis.hash());
+        assert_eq!(store.best_number(), 3);
+        assert_eq!(store.best_hash(), blocks[2].hash());
+        assert_eq!(store.hash(2), Some(blocks[1].hash()));
+        assert_eq!(
+            store.block(BlockId::Number(1)).unwrap().hash(),
+            blocks[0].hash()
+        );
+        assert_eq!(
+            store
+                .header(BlockId::Hash(blocks[1].hash()))
+                .unwrap()
+                .block_number,
+            2
+        );
+        assert_eq!(
+            hash_of(store.extrinsic(BlockId::Number(3), 0).unwrap()),
+            hash_of(&blocks[2].extrinsics[0])
+        );
+        assert!(store.extrinsic(BlockId::Number(3), 1).is_none());
+        assert!(store.block(BlockId::Number(4)).is_none());
+        assert!(store.block(BlockId::Hash(H256::default())).is_none());
+
+        // The blocks do not build on another genesis.
+        let mut other = genesis;
+        other.block_number = 10;
+        assert!(matches!(
+            ChainStore::open(&path, other, Rc::new(LongestChain)),
+            Err(Error::Corrupt(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn best_chain_is_picked_by_the_fork_choice_rule() {
+        let dir = test_dir("chain-forks");
+        let path = dir.join("blocks.log");
+        let genesis = Runtime::from_genesis(&Storage::default(), &test_genesis());
+        let child = |parent: &types::Header, salt: u8| types::Block {
+            header: types::Header {
+                parent_hash: parent.hash(),
+                block_number: parent.block_number + 1,
+                state_root: H256([salt; 32]),
+                extrinsics_root: H256::default(),
+                author: None,
+            },
+            extrinsics: Vec::new(),
+        };
+        let a1 = child(&genesis.genesis_header(), 1);
+        let b1 = child(&genesis.genesis_header(), 2);
+        let b2 = child(&b1.header, 3);
+        let (first, second) = if a1.hash() < b1.hash() {
+            (&a1, &b1)
+        } else {
+            (&b1, &a1)
+        };
+
+        // Whatever the order of the blocks, the same block is the best.
+        for order in [[&a1, &b1], [&b1, &a1]] {
+            let _ = fs::remove_file(&path);
+            let mut store =
+                ChainStore::open(&path, genesis.genesis_header(), Rc::new(LongestChain)).unwrap();
+            for block in order {
+                store.import(block.clone(), Changes::new()).unwrap();
+            }
+            assert_eq!(store.best_hash(), first.hash());
+            assert_eq!(store.hash(1), Some(first.hash()));
+        }
+
+        // A longer chain is better.
+        let mut store =
+            ChainStore::open(&path, genesis.genesis_header(), Rc::new(LongestChain)).unwrap();
+        store.import(b2.clone(), Changes::new()).unwrap();
+        assert_eq!(store.best_hash(), b2.hash());
+        assert_eq!(store.hash(1), Some(b1.hash()));
+        assert_eq!(
+            store.route(a1.hash(), b2.hash()),
+            (vec![a1.hash()], vec![b1.hash(), b2.hash()])
+        );
+        assert_eq!(store.route(b2.hash(), b1.hash()), (vec![b2.hash()], vec![]));
+
+        // Blocks outside of the best chain are only found by hash.
+        assert!(store.block(BlockId::Hash(second.hash())).is_some());
+        assert_eq!(store.find(1, a1.header.state_root), Some(a1.hash()));
+        drop(store);
+
+        let store =
+            ChainStore::open(&path, genesis.genesis_header(), Rc::new(LongestChain)).unwrap();
+        assert_eq!(store.best_hash(), b2.hash());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,698 @@
+use crate::chain::BlockId;
+use crate::node::{self, Node};
+use crate::support::{
+    codec::{Decode, Encode},
+    crypto::Pair,
+    parse_hex, to_hex, H256,
+};
+use crate::{
+    balances, development_genesis, proof_of_existance, rpc, sudo, system, types, ws, Runtime,
+    RuntimeCall, RuntimeEvent, RuntimeGenesisConfig,
+};
+use clap::{Args, Parser, Subcommand};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::{
+    fmt, fs,
+    io::{self, BufWriter, Read, Write},
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+/// The directory where the node keeps the chain, by default.
+const BASE_PATH: &str = "chain-data";
+
+/// Run a node of the chain, or script a running one.
+///
+/// Exits with code 0 on success, 1 if the command failed, and 2 if the arguments are invalid.
+#[derive(Debug, Parser)]
+#[command(name = "mine", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run a node, which authors a block out of its pool every block time.
+    Run {
+        #[command(flatten)]
+        chain: ChainArgs,
+        /// The time between two blocks, in milliseconds.
+        #[arg(
+            long,
+            value_name = "MS",
+            default_value_t = 1000,
+            value_parser = clap::value_parser!(u64).range(1..)
+        )]
+        block_time: u64,
+        /// The port of the JSON-RPC server over HTTP, on localhost.
+        #[arg(long, default_value_t = rpc::DEFAULT_RPC_PORT)]
+        rpc_port: u16,
+        /// The maximum number of connections the JSON-RPC server over HTTP serves at once.
+        #[arg(long, default_value_t = rpc::DEFAULT_MAX_CONNECTIONS)]
+        rpc_max_connections: usize,
+        /// An origin browsers may call the JSON-RPC server over HTTP from, such as
+        /// `http://localhost:3000`, or `*` for any origin. Can be given more than once.
+        #[arg(long, value_name = "ORIGIN")]
+        rpc_cors: Vec<String>,
+        /// The port of the JSON-RPC server over WebSocket, on localhost.
+        #[arg(long, default_value_t = ws::DEFAULT_WS_PORT)]
+        ws_port: u16,
+        /// The account credited with the fees of the authored blocks. Without one, the fees are
+        /// burned.
+        #[arg(long, value_parser = parse_account)]
+        author: Option<types::AccountID>,
+    },
+    /// Sign an extrinsic and submit it to a running node. Prints the hash of the extrinsic.
+    Submit {
+        #[command(flatten)]
+        rpc: RpcArgs,
+        /// The development name, such as Alice, or the `0x` prefixed secret seed of the signer.
+        #[arg(long, value_parser = parse_signer, default_value = "Alice")]
+        signer: Signer,
+        /// The nonce of the extrinsic, instead of the next nonce of the signer.
+        #[arg(long)]
+        nonce: Option<types::Nonce>,
+        #[command(subcommand)]
+        call: Call,
+    },
+    /// Read the state of a running node at its best block.
+    Query {
+        #[command(flatten)]
+        rpc: RpcArgs,
+        #[command(subcommand)]
+        query: Query,
+    },
+    /// Write the blocks of the best chain to a file, SCALE encoded one after the other.
+    ExportBlocks {
+        #[command(flatten)]
+        chain: ChainArgs,
+        /// The first block to export.
+        #[arg(long, default_value_t = 1)]
+        from: types::BlockNumber,
+        /// The last block to export, instead of the best block.
+        #[arg(long)]
+        to: Option<types::BlockNumber>,
+        /// The file to write, instead of the standard output.
+        output: Option<PathBuf>,
+    },
+    /// Import the blocks of a file written by `export-blocks`. Known blocks are skipped.
+    ImportBlocks {
+        #[command(flatten)]
+        chain: ChainArgs,
+        /// The file to read, instead of the standard input.
+        input: Option<PathBuf>,
+    },
+    /// Print every entry of the state at the best block as JSON.
+    ExportState {
+        #[command(flatten)]
+        chain: ChainArgs,
+    },
+    /// Remove the chain kept by a node.
+    PurgeChain {
+        /// The directory where the node keeps the chain.
+        #[arg(long, default_value = BASE_PATH)]
+        base_path: PathBuf,
+        /// Do not ask for confirmation.
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Describe the runtime as JSON.
+    Metadata,
+}
+
+/// Where the chain is kept, and how it started.
+#[derive(Debug, Args)]
+struct ChainArgs {
+    /// The directory where the node keeps the chain.
+    #[arg(long, default_value = BASE_PATH)]
+    base_path: PathBuf,
+    /// The JSON file holding the genesis configuration, instead of the development genesis.
+    #[arg(long)]
+    genesis: Option<PathBuf>,
+}
+
+/// How to reach a running node.
+#[derive(Debug, Args)]
+struct RpcArgs {
+    /// The address of the JSON-RPC server of the node, over HTTP.
+    #[arg(long, default_value_t = SocketAddr::from((Ipv4Addr::LOCALHOST, rpc::DEFAULT_RPC_PORT)))]
+    rpc_addr: SocketAddr,
+}
+
+/// The calls which can be submitted.
+#[derive(Debug, Subcommand)]
+enum Call {
+    /// Transfer tokens to another account.
+    Transfer {
+        #[arg(value_parser = parse_account)]
+        to: types::AccountID,
+        amount: types::Tokens,
+    },
+    /// Claim some content, if no one claimed it yet.
+    CreateClaim { claim: String },
+    /// Revoke a claim of the signer.
+    RevokeClaim { claim: String },
+    /// Hand the sudo key to another account, if the signer holds it.
+    SetSudoKey {
+        #[arg(value_parser = parse_account)]
+        new: types::AccountID,
+    },
+}
+
+/// The parts of the state which can be read.
+#[derive(Debug, Subcommand)]
+enum Query {
+    /// The free balance of an account.
+    Balance {
+        #[arg(value_parser = parse_account)]
+        account: types::AccountID,
+    },
+    /// The owner of a claim, or `none`.
+    Claim { claim: String },
+    /// The nonce of the next extrinsic of an account, counting those in the pool of the node.
+    Nonce {
+        #[arg(value_parser = parse_account)]
+        account: types::AccountID,
+    },
+}
+
+/// The key pair which signs an extrinsic.
+#[derive(Debug, Clone)]
+enum Signer {
+    Name(String),
+    Seed([u8; 32]),
+}
+
+/// The reasons a command can fail.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Node(node::Error),
+    /// The genesis file cannot be read.
+    Genesis(String),
+    /// There is no chain in the base path.
+    NoChain(PathBuf),
+    /// The node answered a call with an error.
+    Rpc(rpc::Error),
+    /// A block of the file being imported cannot be decoded.
+    InvalidBlocks(&'static str),
+    /// The node rejected an imported block.
+    Import(types::BlockNumber, node::Error),
+    /// The command was not confirmed.
+    Aborted,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Node(e) => write!(f, "{}", e),
+            Error::Genesis(reason) => write!(f, "invalid genesis file: {}", reason),
+            Error::NoChain(path) => write!(f, "no chain in {}", path.display()),
+            Error::Rpc(e) => write!(f, "the node answered: {}", e),
+            Error::InvalidBlocks(reason) => write!(f, "invalid blocks file: {}", reason),
+            Error::Import(number, e) => write!(f, "cannot import block {}: {}", number, e),
+            Error::Aborted => write!(f, "aborted"),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<node::Error> for Error {
+    fn from(e: node::Error) -> Self {
+        Error::Node(e)
+    }
+}
+
+impl Cli {
+    /// Run the command given on the command line.
+    pub fn run(self) -> Result<(), Error> {
+        match self.command {
+            Command::Run {
+                chain,
+                block_time,
+                rpc_port,
+                rpc_max_connections,
+                rpc_cors,
+                ws_port,
+                author,
+            } => {
+                let config = node::Config {
+                    block_time: Duration::from_millis(block_time),
+                    author,
+                    ..Default::default()
+                };
+                let rpc = rpc::Config {
+                    port: rpc_port,
+                    max_connections: rpc_max_connections,
+                    cors: rpc_cors,
+                };
+                run_node(chain.open(config)?, rpc, ws_port)
+            }
+            Command::Submit {
+                rpc,
+                signer,
+                nonce,
+                call,
+            } => submit(rpc.rpc_addr, &signer.pair(), nonce, call.into()),
+            Command::Query { rpc, query } => query_state(rpc.rpc_addr, query),
+            Command::ExportBlocks {
+                chain,
+                from,
+                to,
+                output,
+            } => export_blocks(&chain.open_read_only()?, from, to, output.as_deref()),
+            Command::ImportBlocks { chain, input } => {
+                import_blocks(&mut chain.open(node::Config::default())?, input.as_deref())
+            }
+            Command::ExportState { chain } => export_state(&chain.open_read_only()?),
+            Command::PurgeChain { base_path, yes } => purge_chain(&base_path, yes),
+            Command::Metadata => {
+                let metadata = serde_json::to_string_pretty(&Runtime::metadata()).unwrap();
+                println!("{}", metadata);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl ChainArgs {
+    fn genesis(&self) -> Result<RuntimeGenesisConfig, Error> {
+        let Some(path) = &self.genesis else {
+            return Ok(development_genesis());
+        };
+        let json = fs::read_to_string(path).map_err(|e| Error::Genesis(e.to_string()))?;
+        serde_json::from_str(&json).map_err(|e| Error::Genesis(e.to_string()))
+    }
+
+    /// Open the chain, or start it from its genesis if there is none.
+    fn open(&self, config: node::Config) -> Result<Node, Error> {
+        Ok(Node::open(&self.base_path, &self.genesis()?, config)?)
+    }
+
+    /// Open the chain, which must have been started already, without writing to it.
+    fn open_read_only(&self) -> Result<Node, Error> {
+        let genesis = self.genesis()?;
+        match Node::open_read_only(&self.base_path, &genesis, node::Config::default()) {
+            Err(node::Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                Err(Error::NoChain(self.base_path.clone()))
+            }
+            result => Ok(result?),
+        }
+    }
+}
+
+impl From<Call> for RuntimeCall {
+    fn from(call: Call) -> Self {
+        match call {
+            Call::Transfer { to, amount } => {
+                RuntimeCall::balances(balances::Call::transfer { to, amount })
+            }
+            Call::CreateClaim { claim } => {
+                RuntimeCall::proof_of_existance(proof_of_existance::Call::create_claim { claim })
+            }
+            Call::RevokeClaim { claim } => {
+                RuntimeCall::proof_of_existance(proof_of_existance::Call::revoke_claim { claim })
+            }
+            Call::SetSudoKey { new } => RuntimeCall::sudo(sudo::Call::set_key { new }),
+        }
+    }
+}
+
+impl Signer {
+    fn pair(&self) -> Pair {
+        match self {
+            Signer::Name(name) => Pair::from_name(name),
+            Signer::Seed(seed) => Pair::from_seed(*seed),
+        }
+    }
+}
+
+/// Read a signer from its `0x` prefixed secret seed, or from its development name.
+fn parse_signer(s: &str) -> Result<Signer, String> {
+    if s.starts_with("0x") {
+        parse_hex(s)
+            .map(Signer::Seed)
+            .ok_or_else(|| "invalid secret seed".to_string())
+    } else {
+        Ok(Signer::Name(s.to_string()))
+    }
+}
+
+/// Read an account from its `0x` prefixed public key, or from its development name.
+fn parse_account(s: &str) -> Result<types::AccountID, String> {
+    if s.starts_with("0x") {
+        s.parse().map_err(str::to_string)
+    } else {
+        Ok(Pair::from_name(s).public())
+    }
+}
+
+/// Answer RPC calls until the next block is due, then author it out of the ready transactions of
+/// the pool, forever.
+fn run_node(mut node: Node, rpc: rpc::Config, ws_port: u16) -> Result<(), Error> {
+    // scripts and front-ends talk to the node through JSON-RPC, over HTTP or over WebSocket to
+    // also subscribe to new blocks
+    let (sender, requests) = mpsc::channel();
+    let addr = rpc::start(rpc, sender.clone())?;
+    println!("RPC server listening on http://{}", addr);
+    let addr = ws::start(ws_port, sender)?;
+    println!("WebSocket server listening on ws://{}", addr);
+
+    loop {
+        let due = node.next_block_due();
+        rpc::serve_until(&mut node, &requests, due);
+        node.author_block()?;
+
+        let chain = node.chain();
+        let best = chain.best_hash();
+        let header = chain.header(BlockId::Hash(best)).unwrap();
+        println!(
+            "imported block {} {:?} with state root {:?}",
+            header.block_number, best, header.state_root
+        );
+        println!("{:?}", node.pool().status());
+        for record in node.runtime().system.events() {
+            println!("{:?}", record);
+            // show which extrinsic failed, from the copy of the block kept by the chain
+            if let (
+                system::Phase::ApplyExtrinsic(index),
+                RuntimeEvent::system(system::Event::ExtrinsicFailed { .. }),
+            ) = (record.phase, &record.event)
+            {
+                let block = BlockId::Number(header.block_number);
+                let extrinsic = chain.extrinsic(block, index as usize);
+                println!("\tfailed extrinsic: {:?}", extrinsic.unwrap());
+            }
+        }
+    }
+}
+
+/// Call `method` on the node listening on `addr`, and read its answer as a `T`.
+fn request<T: DeserializeOwned>(addr: SocketAddr, method: &str, params: Value) -> Result<T, Error> {
+    let result = rpc::call(addr, method, params)?.map_err(Error::Rpc)?;
+    serde_json::from_value(result)
+        .map_err(|e| Error::Io(io::Error::new(io::ErrorKind::InvalidData, e)))
+}
+
+fn submit(
+    addr: SocketAddr,
+    signer: &Pair,
+    nonce: Option<types::Nonce>,
+    call: RuntimeCall,
+) -> Result<(), Error> {
+    let nonce = match nonce {
+        Some(nonce) => nonce,
+        None => request(addr, "system_accountNonce", json!([signer.public()]))?,
+    };
+    let genesis_hash = request::<H256>(addr, "chain_getGenesisHash", json!([]))?;
+    let extrinsic = types::Extrinsic::new_signed(call, nonce, signer, genesis_hash);
+    let params = json!([to_hex(&extrinsic.encode())]);
+    let hash = request::<H256>(addr, "author_submitExtrinsic", params)?;
+    println!("{:?}", hash);
+    Ok(())
+}
+
+fn query_state(addr: SocketAddr, query: Query) -> Result<(), Error> {
+    match query {
+        Query::Balance { account } => {
+            let balance = request::<String>(addr, "state_getBalance", json!([account]))?;
+            println!("{}", balance);
+        }
+        Query::Claim { claim } => {
+            match request::<Option<types::AccountID>>(addr, "state_getClaim", json!([claim]))? {
+                Some(owner) => println!("{:?}", owner),
+                None => println!("none"),
+            }
+        }
+        Query::Nonce { account } => {
+            let nonce = request::<types::Nonce>(addr, "system_accountNonce", json!([account]))?;
+            println!("{}", nonce);
+        }
+    }
+    Ok(())
+}
+
+/// Write the blocks of the best chain from `from` up to `to`, or up to the best block, to `output`
+/// or to the standard output.
+fn export_blocks(
+    node: &Node,
+    from: types::BlockNumber,
+    to: Option<types::BlockNumber>,
+    output: Option<&Path>,
+) -> Result<(), Error> {
+    let chain = node.chain();
+    // The genesis is not a block which can be imported.
+    let from = from.max(chain.genesis_number() + 1);
+    let to = to.unwrap_or(chain.best_number()).min(chain.best_number());
+    let mut writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(BufWriter::new(fs::File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    for number in from..=to {
+        let block = chain.block(BlockId::Number(number)).unwrap();
+        writer.write_all(&block.encode())?;
+    }
+    writer.flush()?;
+    eprintln!("exported {} blocks", (from..=to).count());
+    Ok(())
+}
+
+/// Import the blocks read from `input`, or from the standard input.
+fn import_blocks(node: &mut Node, input: Option<&Path>) -> Result<(), Error> {
+    let bytes = match input {
+        Some(path) => fs::read(path)?,
+        None => {
+            let mut bytes = Vec::new();
+            io::stdin().lock().read_to_end(&mut bytes)?;
+            bytes
+        }
+    };
+    let mut input = bytes.as_slice();
+    let (mut imported, mut known) = (0, 0);
+    while !input.is_empty() {
+        let block = types::Block::decode(&mut input).map_err(|e| Error::InvalidBlocks(e.0))?;
+        let number = block.header.block_number;
+        match node.import_block(block) {
+            Ok(()) => imported += 1,
+            Err(node::Error::KnownBlock) => known += 1,
+            Err(e) => return Err(Error::Import(number, e)),
+        }
+    }
+    eprintln!(
+        "imported {} blocks, skipped {} known blocks",
+        imported, known
+    );
+    Ok(())
+}
+
+/// Print the state at the best block, with every entry as `0x` prefixed hex.
+fn export_state(node: &Node) -> Result<(), Error> {
+    let chain = node.chain();
+    let storage = node.runtime().system.storage();
+    let entries = storage
+        .iter_prefix(&[])
+        .into_iter()
+        .map(|(key, value)| (to_hex(&key), json!(to_hex(&value))))
+        .collect::<serde_json::Map<_, _>>();
+    let state = json!({
+        "block_number": chain.best_number(),
+        "block_hash": chain.best_hash(),
+        "state_root": storage.root(),
+        "storage": entries,
+    });
+    // The state can be large, so a closed output is an error rather than a panic.
+    writeln!(io::stdout().lock(), "{:#}", state)?;
+    Ok(())
+}
+
+fn purge_chain(base_path: &Path, yes: bool) -> Result<(), Error> {
+    if !yes {
+        eprint!("Remove the chain in {}? [y/N] ", base_path.display());
+        io::stderr().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            return Err(Error::Aborted);
+        }
+    }
+    if Node::purge(base_path)? {
+        eprintln!("removed the chain in {}", base_path.display());
+    } else {
+        eprintln!("no chain in {}", base_path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::support::db::test_dir;
+    use crate::tests::{account, test_genesis, transfer_block};
+    use std::thread;
+
+    #[test]
+    fn arguments_are_parsed() {
+        let cli = Cli::try_parse_from([
+            "mine", "submit", "--signer", "Bob", "transfer", "Charlie", "100",
+        ])
+        .unwrap();
+        let Command::Submit { signer, call, .. } = cli.command else {
+            panic!("not a submit command");
+        };
+        assert_eq!(signer.pair().public(), account("Bob"));
+        assert!(matches!(
+            call,
+            Call::Transfer { to, amount: 100 } if to == account("Charlie")
+        ));
+
+        let key = format!("{:?}", account("Dave"));
+        let cli = Cli::try_parse_from(["mine", "query", "balance", &key]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Query { query: Query::Balance { account: a }, .. } if a == account("Dave")
+        ));
+
+        // Invalid arguments are rejected with the usage exit code.
+        let error = Cli::try_parse_from(["mine", "query", "balance", "0x12"]).unwrap_err();
+        assert_eq!(error.exit_code(), 2);
+        let error = Cli::try_parse_from(["mine", "run", "--block-time", "soon"]).unwrap_err();
+        assert_eq!(error.exit_code(), 2);
+        let error = Cli::try_parse_from(["mine", "run", "--block-time", "0"]).unwrap_err();
+        assert_eq!(error.exit_code(), 2);
+    }
+
+    #[test]
+    fn blocks_are_exported_and_imported() {
+        let dir = test_dir("cli-export");
+        let mut node =
+            Node::open(&dir.join("a"), &test_genesis(), node::Config::default()).unwrap();
+        for amount in [10, 20, 30] {
+            let block = transfer_block(node.runtime(), "Bob", amount);
+            node.import_block(block).unwrap();
+        }
+        let file = dir.join("blocks.bin");
+        export_blocks(&node, 0, None, Some(&file)).unwrap();
+
+        let mut copy =
+            Node::open(&dir.join("b"), &test_genesis(), node::Config::default()).unwrap();
+        import_blocks(&mut copy, Some(&file)).unwrap();
+        assert_eq!(copy.chain().best_hash(), node.chain().best_hash());
+        assert_eq!(copy.runtime().state_root(), node.runtime().state_root());
+        // Importing again skips the known blocks.
+        import_blocks(&mut copy, Some(&file)).unwrap();
+        assert_eq!(copy.chain().best_number(), 3);
+
+        // A part of the chain can be exported.
+        export_blocks(&node, 2, Some(2), Some(&file)).unwrap();
+        let block = types::Block::decode_all(&fs::read(&file).unwrap()).unwrap();
+        assert_eq!(block.header.block_number, 2);
+        // Blocks cannot be imported without their parent.
+        let mut other =
+            Node::open(&dir.join("c"), &test_genesis(), node::Config::default()).unwrap();
+        assert!(matches!(
+            import_blocks(&mut other, Some(&file)),
+            Err(Error::Import(2, node::Error::UnknownParent))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn extrinsics_are_submitted_to_a_running_node() {
+        let dir = test_dir("cli-submit");
+        let mut node = Node::open(&dir, &test_genesis(), node::Config::default()).unwrap();
+        let (sender, requests) = mpsc::channel();
+        let config = rpc::Config {
+            port: 0,
+            ..Default::default()
+        };
+        let addr = rpc::start(config, sender).unwrap();
+
+        let client = thread::spawn(move || {
+            let transfer = balances::Call::transfer {
+                to: account("Bob"),
+                amount: 30,
+            };
+            submit(
+                addr,
+                &Pair::from_name("Alice"),
+                None,
+                RuntimeCall::balances(transfer),
+            )
+        });
+        // The client asks for the nonce and the genesis hash, then submits.
+        for _ in 0..3 {
+            let request = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+            rpc::serve(&mut node, request);
+        }
+        client.join().unwrap().unwrap();
+        assert_eq!(node.pool().status().ready, 1);
+        node.author_block().unwrap();
+        assert_eq!(node.runtime().balances.get_balance(&account("Bob")), 30);
+
+        // A rejected extrinsic is an error.
+        let client = thread::spawn(move || {
+            let claim = proof_of_existance::Call::revoke_claim {
+                claim: "missing".to_string(),
+            };
+            let call = RuntimeCall::proof_of_existance(claim);
+            submit(addr, &Pair::from_name("Nobody"), Some(0), call)
+        });
+        for _ in 0..2 {
+            let request = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+            rpc::serve(&mut node, request);
+        }
+        assert!(matches!(
+            client.join().unwrap(),
+            Err(Error::Rpc(rpc::Error { code: 1010, .. }))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn extrinsics_are_submitted_to_a_chain_starting_after_block_zero() {
+        let dir = test_dir("cli-submit-genesis");
+        let genesis = RuntimeGenesisConfig {
+            system: system::GenesisConfig { block_number: 5 },
+            ..test_genesis()
+        };
+        let mut node = Node::open(&dir, &genesis, node::Config::default()).unwrap();
+        let (sender, requests) = mpsc::channel();
+        let config = rpc::Config {
+            port: 0,
+            ..Default::default()
+        };
+        let addr = rpc::start(config, sender).unwrap();
+
+        let client = thread::spawn(move || {
+            let transfer = balances::Call::transfer {
+                to: account("Bob"),
+                amount: 30,
+            };
+            submit(
+                addr,
+                &Pair::from_name("Alice"),
+                Some(0),
+                RuntimeCall::balances(transfer),
+            )
+        });
+        for _ in 0..2 {
+            let request = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+            rpc::serve(&mut node, request);
+        }
+        client.join().unwrap().unwrap();
+        let block = node.author_block().unwrap();
+        assert_eq!(block.header.block_number, 6);
+        assert_eq!(node.runtime().balances.get_balance(&account("Bob")), 30);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,83 +1,944 @@
-use support::Dispatch;
+use clap::Parser;
+use std::process::ExitCode;
+use support::{crypto::Pair, Dispatch, GetWeight};
 
 mod balances;
+mod builder;
+mod chain;
+mod cli;
+mod node;
+mod pool;
 mod proof_of_existance;
+mod rpc;
+mod sudo;
 mod support;
 mod system;
+mod ws;
 
 mod types {
-    pub type AccountID = String;
+    pub type AccountID = crate::support::crypto::Public;
     pub type Tokens = u128;
     pub type BlockNumber = u32;
     pub type Nonce = u32;
 
-    pub type Extrinsic = crate::support::Extrinsic<AccountID, crate::RuntimeCall>;
+    pub type Extrinsic = crate::support::Extrinsic<Nonce, crate::RuntimeCall>;
     pub type Header = crate::support::Header<BlockNumber>;
     pub type Block = crate::support::Block<Header, Extrinsic>;
 }
 
 // configure our runtime
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[macros::runtime]
 pub struct Runtime {
     system: system::Pallet<Self>,
     balances: balances::Pallet<Self>,
     proof_of_existance: proof_of_existance::Pallet<Self>,
+    sudo: sudo::Pallet<Self>,
 }
 impl system::Config for Runtime {
     type AccountId = types::AccountID;
     type BlockNumber = types::BlockNumber;
     type Nonce = types::Nonce;
+    type RuntimeEvent = RuntimeEvent;
+    type RuntimeCall = RuntimeCall;
+    type RuntimeOrigin = RuntimeOrigin;
+    const MAX_BLOCK_WEIGHT: support::Weight = 1_000;
 }
 impl balances::Config for Runtime {
     type Tokens = types::Tokens;
+    const FEE_PER_WEIGHT: types::Tokens = 1;
+    const FEE_PER_BYTE: types::Tokens = 1;
+    const FEE_DESTINATION: balances::FeeDestination = balances::FeeDestination::BlockAuthor;
 }
 impl proof_of_existance::Config for Runtime {
-    type Content = &'static str;
+    type Content = String;
+}
+impl sudo::Config for Runtime {}
+
+// Transaction fees are paid in the main token, based on the weight of the call.
+impl support::ChargeTransaction for Runtime {
+    type AccountId = types::AccountID;
+    type Call = RuntimeCall;
+
+    fn charge_transaction(
+        &mut self,
+        who: &Self::AccountId,
+        call: &Self::Call,
+        len: usize,
+    ) -> support::DispatchResult {
+        let author = self.system.block_author().copied();
+        self.balances
+            .charge_fee(who, author.as_ref(), call.weight(), len)
+    }
 }
 
 // use runtime in main logic
 
-const NAMES: [&str; 10] = [
-    "Alice", "Bob", "Charlie", "Dave", "Eve", "Ferdie", "Grace", "Hank", "Ivy", "Judy",
-];
-
-fn main() {
-    // initialize runtime
-    let mut runtime = Runtime::new();
-    runtime.balances.set_balance(&"Alice".to_string(), 100);
-
-    let mut idx = 1;
-    loop {
-        // define block content:
-        // - alice sends 30 tokens to some account
-        let to = NAMES[idx as usize % 10];
-        let amount = 30;
-        let mut block = types::Block {
-            header: types::Header { block_number: idx },
-            extrinsics: vec![types::Extrinsic {
-                caller: "Alice".to_string(),
-                call: RuntimeCall::balances(balances::Call::transfer {
-                    to: to.to_string(),
-                    amount,
+/// A development genesis where Alice holds enough tokens to pay for a long running chain, and is
+/// the sudo key.
+fn development_genesis() -> RuntimeGenesisConfig {
+    RuntimeGenesisConfig {
+        balances: balances::GenesisConfig {
+            balances: vec![(Pair::from_name("Alice").public(), 1_000_000_000)],
+        },
+        sudo: sudo::GenesisConfig {
+            key: Some(Pair::from_name("Alice").public()),
+        },
+        ..Default::default()
+    }
+}
+
+fn main() -> ExitCode {
+    // invalid arguments exit with the usage of the command
+    match cli::Cli::parse().run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::support::{
+        codec::{Decode, Encode},
+        storage::Storage,
+        DispatchError, H256,
+    };
+
+    pub fn account(name: &str) -> types::AccountID {
+        Pair::from_name(name).public()
+    }
+
+    /// A genesis where Alice holds enough tokens for any test, and is the sudo key.
+    pub fn test_genesis() -> RuntimeGenesisConfig {
+        RuntimeGenesisConfig {
+            balances: balances::GenesisConfig {
+                balances: vec![(account("Alice"), 1_000_000)],
+            },
+            sudo: sudo::GenesisConfig {
+                key: Some(account("Alice")),
+            },
+            ..Default::default()
+        }
+    }
+
+    fn new_runtime() -> Runtime {
+        Runtime::from_genesis(&Storage::default(), &test_genesis())
+    }
+
+    pub fn seal_block(runtime: &Runtime, extrinsics: Vec<types::Extrinsic>) -> types::Block {
+        let mut builder =
+            builder::BlockBuilder::new(runtime, None, builder::DEFAULT_MAX_BLOCK_SIZE);
+        for extrinsic in extrinsics {
+            let _ = builder.push(extrinsic).expect("invalid extrinsic");
+        }
+        builder.build()
+    }
+
+    pub fn transfer(runtime: &Runtime, to: &str, amount: u128) -> types::Extrinsic {
+        let alice = Pair::from_name("Alice");
+        types::Extrinsic::new_signed(
+            RuntimeCall::balances(balances::Call::transfer {
+                to: account(to),
+                amount,
+            }),
+            runtime.system.nonce(&alice.public()),
+            &alice,
+            runtime.system.genesis_hash(),
+        )
+    }
+
+    pub fn transfer_block(runtime: &Runtime, to: &str, amount: u128) -> types::Block {
+        seal_block(runtime, vec![transfer(runtime, to, amount)])
+    }
+
+    #[test]
+    fn rejected_block_leaves_state_untouched() {
+        let mut runtime = new_runtime();
+
+        let mut block = transfer_block(&runtime, "Bob", 30);
+        block.header.block_number = 2;
+        assert_eq!(
+            runtime.execute_block(block),
+            Err(DispatchError::Other(
+                "block number does not match what is expected"
+            ))
+        );
+        assert_eq!(runtime.system.block_number(), 0);
+        assert_eq!(runtime.balances.get_balance(&account("Bob")), 0);
+
+        let block = transfer_block(&runtime, "Bob", 30);
+        assert_eq!(runtime.execute_block(block), Ok(()));
+        assert_eq!(runtime.system.block_number(), 1);
+        assert_eq!(runtime.balances.get_balance(&account("Bob")), 30);
+    }
+
+    #[test]
+    fn blocks_are_linked_by_hash() {
+        let mut runtime = new_runtime();
+
+        let block = transfer_block(&runtime, "Bob", 30);
+        let hash = block.hash();
+        assert_eq!(runtime.execute_block(block), Ok(()));
+        assert_eq!(runtime.system.parent_hash(), hash);
+
+        // A block which does not build on the last block is rejected.
+        let mut block = transfer_block(&runtime, "Bob", 30);
+        block.header.parent_hash = H256::default();
+        assert_eq!(
+            runtime.execute_block(block),
+            Err(DispatchError::Other(
+                "block does not build on the last executed block"
+            ))
+        );
+
+        // A block whose body was tampered with is rejected.
+        let mut block = transfer_block(&runtime, "Bob", 30);
+        block.extrinsics.clear();
+        assert_eq!(
+            runtime.execute_block(block),
+            Err(DispatchError::Other(
+                "extrinsics root does not match the block body"
+            ))
+        );
+
+        assert_eq!(runtime.system.parent_hash(), hash);
+        assert_eq!(runtime.balances.get_balance(&account("Bob")), 30);
+    }
+
+    #[test]
+    fn state_root_is_checked() {
+        let mut runtime = new_runtime();
+        let root = runtime.state_root();
+
+        // A block which lies about the resulting state is rejected.
+        let mut block = transfer_block(&runtime, "Bob", 30);
+        block.header.state_root = root;
+        assert_eq!(
+            runtime.execute_block(block),
+            Err(DispatchError::Other(
+                "state root does not match the result of the block"
+            ))
+        );
+        assert_eq!(runtime.state_root(), root);
+        assert_eq!(runtime.system.block_number(), 0);
+
+        let block = transfer_block(&runtime, "Bob", 30);
+        let expected_root = block.header.state_root;
+        assert_eq!(runtime.execute_block(block), Ok(()));
+        assert_eq!(runtime.state_root(), expected_root);
+    }
+
+    #[test]
+    fn block_weight_is_limited() {
+        let mut runtime = new_runtime();
+        let alice = Pair::from_name("Alice");
+        let call = RuntimeCall::balances(balances::Call::transfer {
+            to: account("Bob"),
+            amount: 1,
+        });
+        assert_eq!(call.weight(), 100);
+
+        let genesis_hash = runtime.system.genesis_hash();
+        let transfers = |count: u32| {
+            (0..count)
+                .map(|nonce| {
+                    types::Extrinsic::new_signed(call.clone(), nonce, &alice, genesis_hash)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // One transfer too many does not fit in a block.
+        let mut builder =
+            builder::BlockBuilder::new(&runtime, None, builder::DEFAULT_MAX_BLOCK_SIZE);
+        let mut extrinsics = transfers(11);
+        let last = extrinsics.pop().unwrap();
+        for extrinsic in extrinsics {
+            assert_eq!(builder.push(extrinsic), Ok(Ok(())));
+        }
+        assert_eq!(builder.push(last), Err(builder::Error::ExhaustsResources));
+        let mut block = builder.build();
+        block.extrinsics = transfers(11);
+        block.header.extrinsics_root = block.extrinsics_root();
+        let events = runtime.system.events().len();
+        assert_eq!(
+            runtime.execute_block(block),
+            Err(DispatchError::Other(
+                "block weight exceeds the maximum block weight"
+            ))
+        );
+        assert_eq!(runtime.system.events().len(), events);
+
+        let block = seal_block(&runtime, transfers(10));
+        assert_eq!(runtime.execute_block(block), Ok(()));
+        assert_eq!(runtime.balances.get_balance(&account("Bob")), 10);
+    }
+
+    #[test]
+    fn extrinsic_cannot_be_replayed() {
+        let mut runtime = new_runtime();
+
+        let block = transfer_block(&runtime, "Bob", 30);
+        let replayed = block.extrinsics[0].clone();
+        assert_eq!(runtime.execute_block(block), Ok(()));
+        assert_eq!(runtime.system.nonce(&account("Alice")), 1);
+
+        let mut block = transfer_block(&runtime, "Bob", 30);
+        block.extrinsics = vec![replayed];
+        block.header.extrinsics_root = block.extrinsics_root();
+        assert_eq!(
+            runtime.execute_block(block),
+            Err(DispatchError::Other(
+                "extrinsic nonce does not match the caller's nonce"
+            ))
+        );
+        assert_eq!(runtime.system.nonce(&account("Alice")), 1);
+        assert_eq!(runtime.balances.get_balance(&account("Bob")), 30);
+    }
+
+    #[test]
+    fn extrinsic_signature_is_checked() {
+        let mut runtime = new_runtime();
+
+        // Bob cannot sign on behalf of Alice.
+        let mut forged = transfer(&runtime, "Bob", 30);
+        forged.signature.as_mut().unwrap().signature = Pair::from_name("Bob").sign(b"transfer");
+        let mut block = transfer_block(&runtime, "Bob", 30);
+        block.extrinsics = vec![forged];
+        block.header.extrinsics_root = block.extrinsics_root();
+        assert_eq!(
+            runtime.execute_block(block),
+            Err(DispatchError::Other("extrinsic signature is invalid"))
+        );
+
+        // An extrinsic signed for another chain is not valid on this one.
+        let other_chain = types::Extrinsic::new_signed(
+            RuntimeCall::balances(balances::Call::transfer {
+                to: account("Bob"),
+                amount: 30,
+            }),
+            0,
+            &Pair::from_name("Alice"),
+            H256::default(),
+        );
+        let mut block = transfer_block(&runtime, "Bob", 30);
+        block.extrinsics = vec![other_chain];
+        block.header.extrinsics_root = block.extrinsics_root();
+        assert_eq!(
+            runtime.execute_block(block),
+            Err(DispatchError::Other("extrinsic signature is invalid"))
+        );
+
+        // None of our pallets accept unsigned calls.
+        let unsigned = types::Extrinsic {
+            signature: None,
+            call: RuntimeCall::balances(balances::Call::transfer {
+                to: account("Bob"),
+                amount: 30,
+            }),
+        };
+        let mut block = transfer_block(&runtime, "Bob", 30);
+        block.extrinsics = vec![unsigned];
+        block.header.extrinsics_root = block.extrinsics_root();
+        assert_eq!(runtime.execute_block(block), Err(DispatchError::BadOrigin));
+
+        assert_eq!(runtime.system.block_number(), 0);
+        assert_eq!(runtime.balances.get_balance(&account("Bob")), 0);
+    }
+
+    #[test]
+    fn events_are_collected_per_block() {
+        let mut runtime = new_runtime();
+
+        let block = seal_block(
+            &runtime,
+            vec![
+                transfer(&runtime, "Bob", 30),
+                types::Extrinsic::new_signed(
+                    RuntimeCall::balances(balances::Call::transfer {
+                        to: account("Bob"),
+                        amount: 10_000_000,
+                    }),
+                    1,
+                    &Pair::from_name("Alice"),
+                    runtime.system.genesis_hash(),
+                ),
+            ],
+        );
+        assert_eq!(runtime.execute_block(block), Ok(()));
+
+        let events = runtime.system.events();
+        assert_eq!(events.len(), 5);
+        assert!(matches!(
+            &events[0],
+            system::EventRecord {
+                phase: system::Phase::ApplyExtrinsic(0),
+                event: RuntimeEvent::balances(balances::Event::FeePaid { .. }),
+            }
+        ));
+        assert!(matches!(
+            &events[1],
+            system::EventRecord {
+                phase: system::Phase::ApplyExtrinsic(0),
+                event: RuntimeEvent::balances(balances::Event::Transfer { amount: 30, .. }),
+            }
+        ));
+        assert!(matches!(
+            &events[2],
+            system::EventRecord {
+                phase: system::Phase::ApplyExtrinsic(0),
+                event: RuntimeEvent::system(system::Event::ExtrinsicSuccess),
+            }
+        ));
+        // A failing call still pays its fee.
+        assert!(matches!(
+            &events[3],
+            system::EventRecord {
+                phase: system::Phase::ApplyExtrinsic(1),
+                event: RuntimeEvent::balances(balances::Event::FeePaid { .. }),
+            }
+        ));
+        assert!(matches!(
+            &events[4],
+            system::EventRecord {
+                phase: system::Phase::ApplyExtrinsic(1),
+                event: RuntimeEvent::system(system::Event::ExtrinsicFailed {
+                    error: DispatchError::Module {
+                        pallet_index: 1,
+                        ..
+                    }
+                }),
+            }
+        ));
+
+        // The events of the previous block are cleared.
+        let block = transfer_block(&runtime, "Charlie", 10);
+        assert_eq!(runtime.execute_block(block), Ok(()));
+        assert_eq!(runtime.system.events().len(), 3);
+    }
+
+    #[test]
+    fn transaction_fees_are_charged() {
+        let mut runtime = new_runtime();
+
+        // Failing calls pay the same fee as successful ones.
+        let ok = transfer(&runtime, "Bob", 30);
+        let fee = balances::Pallet::<Runtime>::compute_fee(100, ok.encode().len()).unwrap();
+        assert!(fee > 100);
+        let failing = types::Extrinsic::new_signed(
+            RuntimeCall::balances(balances::Call::transfer {
+                to: account("Bob"),
+                amount: 10_000_000,
+            }),
+            1,
+            &Pair::from_name("Alice"),
+            runtime.system.genesis_hash(),
+        );
+        let block = seal_block(&runtime, vec![ok, failing]);
+        assert_eq!(runtime.execute_block(block), Ok(()));
+        assert_eq!(
+            runtime.balances.get_balance(&account("Alice")),
+            1_000_000 - 30 - 2 * fee
+        );
+
+        // An extrinsic whose signer cannot pay its fee makes the block invalid.
+        let bob = Pair::from_name("Bob");
+        let unpaid = types::Extrinsic::new_signed(
+            RuntimeCall::balances(balances::Call::transfer {
+                to: account("Alice"),
+                amount: 1,
+            }),
+            0,
+            &bob,
+            runtime.system.genesis_hash(),
+        );
+        let mut block = transfer_block(&runtime, "Bob", 30);
+        block.extrinsics = vec![unpaid];
+        block.header.extrinsics_root = block.extrinsics_root();
+        assert_eq!(
+            runtime.execute_block(block),
+            Err(balances::Error::InsufficientFunds.into())
+        );
+        assert_eq!(runtime.balances.get_balance(&account("Bob")), 30);
+        assert_eq!(runtime.system.nonce(&bob.public()), 0);
+    }
+
+    #[test]
+    fn genesis_from_json() {
+        let json = format!(
+            r#"{{
+                "system": {{ "block_number": 10 }},
+                "balances": {{ "balances": [["{:?}", 100]] }},
+                "proof_of_existance": {{ "claims": [["Hello, World!", "{:?}"]] }}
+            }}"#,
+            account("Alice"),
+            account("Bob")
+        );
+        let config: RuntimeGenesisConfig = serde_json::from_str(&json).unwrap();
+        let runtime = Runtime::from_genesis(&Storage::default(), &config);
+
+        assert_eq!(runtime.system.block_number(), 10);
+        assert_eq!(runtime.balances.get_balance(&account("Alice")), 100);
+        assert_eq!(
+            runtime
+                .proof_of_existance
+                .get_claim(&"Hello, World!".to_string()),
+            Some(account("Bob"))
+        );
+
+        // The genesis is reproducible, and identifies the chain.
+        let again = Runtime::from_genesis(&Storage::default(), &config);
+        assert_eq!(again.system.genesis_hash(), runtime.system.genesis_hash());
+        assert_eq!(runtime.system.parent_hash(), runtime.system.genesis_hash());
+        assert_ne!(
+            new_runtime().system.genesis_hash(),
+            runtime.system.genesis_hash()
+        );
+
+        // Pallets missing from the file use their default configuration.
+        let config: RuntimeGenesisConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(
+            Runtime::from_genesis(&Storage::default(), &config)
+                .system
+                .block_number(),
+            0
+        );
+    }
+
+    #[test]
+    fn dispatch_error_has_pallet_index() {
+        let mut runtime = new_runtime();
+        assert_eq!(
+            runtime.dispatch(
+                system::RawOrigin::Signed(account("Bob")).into(),
+                RuntimeCall::balances(balances::Call::transfer {
+                    to: account("Alice"),
+                    amount: 10,
                 }),
-            }],
+            ),
+            Err(DispatchError::Module {
+                pallet_index: 1,
+                error_index: balances::Error::InsufficientFunds as u8,
+            })
+        );
+        assert_eq!(
+            runtime.dispatch(
+                system::RawOrigin::Signed(account("Alice")).into(),
+                RuntimeCall::proof_of_existance(proof_of_existance::Call::revoke_claim {
+                    claim: "Hello, World!".to_string(),
+                }),
+            ),
+            Err(DispatchError::Module {
+                pallet_index: 2,
+                error_index: proof_of_existance::Error::NoSuchClaim as u8,
+            })
+        );
+    }
+
+    #[test]
+    fn calls_check_their_origin() {
+        let mut runtime = new_runtime();
+        let force_set_balance = RuntimeCall::balances(balances::Call::force_set_balance {
+            who: account("Bob"),
+            amount: 50,
+        });
+
+        assert_eq!(
+            runtime.dispatch(
+                system::RawOrigin::Signed(account("Alice")).into(),
+                force_set_balance.clone(),
+            ),
+            Err(DispatchError::BadOrigin)
+        );
+        assert_eq!(
+            runtime.dispatch(system::RawOrigin::None.into(), force_set_balance.clone()),
+            Err(DispatchError::BadOrigin)
+        );
+        assert_eq!(
+            runtime.dispatch(system::RawOrigin::Root.into(), force_set_balance),
+            Ok(())
+        );
+        assert_eq!(runtime.balances.get_balance(&account("Bob")), 50);
+    }
+
+    #[test]
+    fn sudo_dispatches_as_root() {
+        let mut runtime = new_runtime();
+        let alice = Pair::from_name("Alice");
+        let sudo = |runtime: &Runtime, nonce: types::Nonce, call: RuntimeCall| {
+            types::Extrinsic::new_signed(
+                RuntimeCall::sudo(sudo::Call::sudo {
+                    call: Box::new(call),
+                }),
+                nonce,
+                &alice,
+                runtime.system.genesis_hash(),
+            )
         };
 
-        if rand::random::<f32>() < 0.2 {
-            block.extrinsics.push(types::Extrinsic {
-                caller: NAMES[rand::random::<u32>() as usize % NAMES.len()].to_string(),
-                call: RuntimeCall::proof_of_existance(proof_of_existance::Call::create_claim {
-                    claim: "Hello, World!",
+        // The sudo key fixes Bob's balance, and revokes a claim it does not own. Revoking a claim
+        // which does not exist fails, but the sudo call itself succeeds.
+        let claim = types::Extrinsic::new_signed(
+            RuntimeCall::proof_of_existance(proof_of_existance::Call::create_claim {
+                claim: "Hello, World!".to_string(),
+            }),
+            0,
+            &Pair::from_name("Bob"),
+            runtime.system.genesis_hash(),
+        );
+        let force_revoke = || {
+            RuntimeCall::proof_of_existance(proof_of_existance::Call::force_revoke_claim {
+                claim: "Hello, World!".to_string(),
+            })
+        };
+        let block = seal_block(
+            &runtime,
+            vec![
+                sudo(
+                    &runtime,
+                    0,
+                    RuntimeCall::balances(balances::Call::force_set_balance {
+                        who: account("Bob"),
+                        amount: 1_000,
+                    }),
+                ),
+                claim,
+                sudo(&runtime, 1, force_revoke()),
+                sudo(&runtime, 2, force_revoke()),
+            ],
+        );
+        assert_eq!(runtime.execute_block(block), Ok(()));
+        assert!(runtime.balances.get_balance(&account("Bob")) < 1_000);
+        assert_eq!(
+            runtime
+                .proof_of_existance
+                .get_claim(&"Hello, World!".to_string()),
+            None
+        );
+        let sudo_results = runtime
+            .system
+            .events()
+            .iter()
+            .filter_map(|record| match &record.event {
+                RuntimeEvent::sudo(sudo::Event::Sudid { sudo_result }) => Some(*sudo_result),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sudo_results,
+            vec![
+                Ok(()),
+                Ok(()),
+                Err(DispatchError::Module {
+                    pallet_index: 2,
+                    error_index: proof_of_existance::Error::NoSuchClaim as u8,
+                })
+            ]
+        );
+
+        // Nobody else can use sudo.
+        assert_eq!(
+            runtime.dispatch(
+                system::RawOrigin::Signed(account("Bob")).into(),
+                RuntimeCall::sudo(sudo::Call::set_key {
+                    new: account("Bob")
                 }),
-            });
+            ),
+            Err(DispatchError::Module {
+                pallet_index: 3,
+                error_index: sudo::Error::RequireSudo as u8,
+            })
+        );
+    }
+
+    #[test]
+    fn metadata_describes_the_runtime() {
+        let metadata = Runtime::metadata();
+        let names = metadata
+            .pallets
+            .iter()
+            .map(|pallet| (pallet.name, pallet.index))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![("balances", 1), ("proof_of_existance", 2), ("sudo", 3)]
+        );
+
+        let balances = &metadata.pallets[0];
+        let transfer = &balances.calls[0];
+        assert_eq!((transfer.name, transfer.index), ("transfer", 0));
+        let args = transfer
+            .args
+            .iter()
+            .map(|arg| (arg.name, arg.ty))
+            .collect::<Vec<_>>();
+        assert_eq!(args, vec![("to", "T::AccountId"), ("amount", "T::Tokens")]);
+        assert_eq!(
+            transfer.docs,
+            vec![
+                "Move frunds from one account to another, only if",
+                "requested transfer is valid"
+            ]
+        );
+        assert_eq!(balances.errors[0].name, "InsufficientFunds");
+        assert_eq!(
+            balances.errors[0].index,
+            balances::Error::InsufficientFunds as u8
+        );
+
+        let json = serde_json::to_value(&metadata).unwrap();
+        assert_eq!(
+            json["pallets"][1]["calls"][0]["args"][0]["type"],
+            "T::Content"
+        );
+    }
+
+    #[test]
+    fn calls_and_blocks_are_encoded() {
+        let runtime = new_runtime();
+
+        // A call starts with the index of its pallet, then the index of the call in the pallet.
+        let call = RuntimeCall::balances(balances::Call::transfer {
+            to: account("Bob"),
+            amount: 30,
+        });
+        let encoded = call.encode();
+        assert_eq!(encoded[..2], [1, 0]);
+        assert_eq!(encoded.len(), 2 + 32 + 16);
+        assert_eq!(RuntimeCall::decode_all(&encoded).unwrap().encode(), encoded);
+
+        let block = transfer_block(&runtime, "Bob", 30);
+        let decoded = types::Block::decode_all(&block.encode()).unwrap();
+        assert_eq!(decoded.hash(), block.hash());
+        assert_eq!(decoded.extrinsics_root(), block.header.extrinsics_root);
+        assert!(decoded.extrinsics[0].verify(runtime.system.genesis_hash()));
+
+        // Unknown indices are rejected.
+        assert!(RuntimeCall::decode_all(&[9, 0]).is_err());
+        assert!(RuntimeCall::decode_all(&[1, 9]).is_err());
+    }
+
+    /// A runtime of pallets which record their hooks, to check how the runtime calls them.
+    #[allow(dead_code)]
+    mod hooks {
+        use crate::support::{self, storage::Storage, Dispatch, H256};
+        use crate::system;
+
+        mod types {
+            pub type AccountID = crate::support::crypto::Public;
+            pub type BlockNumber = u32;
+            pub type Nonce = u32;
+
+            pub type Extrinsic = crate::support::Extrinsic<Nonce, super::RuntimeCall>;
+            pub type Header = crate::support::Header<BlockNumber>;
+            pub type Block = crate::support::Block<Header, Extrinsic>;
         }
 
-        // execute block
-        runtime.execute_block(block).expect("invalid block");
-        println!("{:#?}", runtime);
-        std::thread::sleep(std::time::Duration::from_secs(1));
-        idx += 1;
+        /// A pallet which records the hooks called on it, in the order of the calls across every
+        /// pallet of the runtime.
+        mod recorder {
+            use crate::support::{
+                storage::Storage, BuildGenesis, DispatchNested, DispatchResult, Hooks, Weight,
+            };
+            use crate::system::ensure_signed;
+            use serde::{Deserialize, Serialize};
+            use std::{cell::RefCell, marker::PhantomData};
+
+            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+            pub enum Hook {
+                Initialize,
+                /// `on_idle`, with the weight it was offered.
+                Idle(Weight),
+                Finalize,
+            }
+
+            thread_local! {
+                static CALLS: RefCell<Vec<(String, Hook)>> = const { RefCell::new(Vec::new()) };
+            }
+
+            /// Take the hooks called so far on this thread, with the name of the pallet.
+            pub fn take_calls() -> Vec<(String, Hook)> {
+                CALLS.with(|calls| calls.take())
+            }
+
+            pub trait Config: crate::system::Config {}
+
+            #[macros::error]
+            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+            pub enum Error {
+                /// Never returned.
+                Unused,
+            }
+
+            // This pallet deposits no events.
+            pub type Event<T> = PhantomData<T>;
+
+            #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+            pub struct GenesisConfig {
+                /// The name the pallet records its hooks with.
+                pub name: String,
+                /// The weight the pallet uses in `on_idle`, if that much is left.
+                pub idle_weight: Weight,
+            }
+
+            #[derive(Debug, Clone)]
+            pub struct Pallet<T: Config> {
+                config: GenesisConfig,
+                _config: PhantomData<T>,
+            }
+
+            impl<T: Config> Pallet<T> {
+                pub fn new(_storage: &Storage) -> Self {
+                    Self {
+                        config: GenesisConfig::default(),
+                        _config: PhantomData,
+                    }
+                }
+
+                pub fn take_events(&mut self) -> Vec<Event<T>> {
+                    Vec::new()
+                }
+
+                fn record(&self, hook: Hook) {
+                    CALLS.with(|calls| calls.borrow_mut().push((self.config.name.clone(), hook)));
+                }
+            }
+
+            #[macros::call(hooks)]
+            impl<T: Config> Pallet<T> {
+                /// Do nothing.
+                #[weight(0)]
+                pub fn note(
+                    &mut self,
+                    origin: T::RuntimeOrigin,
+                    _who: T::AccountId,
+                ) -> DispatchResult {
+                    ensure_signed(origin)?;
+                    Ok(())
+                }
+            }
+
+            impl<T: Config> Hooks<T::BlockNumber> for Pallet<T> {
+                fn on_initialize(&mut self, _n: T::BlockNumber) {
+                    self.record(Hook::Initialize);
+                }
+
+                fn on_idle(&mut self, _n: T::BlockNumber, remaining_weight: Weight) -> Weight {
+                    self.record(Hook::Idle(remaining_weight));
+                    self.config.idle_weight.min(remaining_weight)
+                }
+
+                fn on_finalize(&mut self, _n: T::BlockNumber) {
+                    self.record(Hook::Finalize);
+                }
+            }
+
+            impl<T: Config> BuildGenesis for Pallet<T> {
+                type GenesisConfig = GenesisConfig;
+
+                fn build_genesis(_storage: &Storage, config: &GenesisConfig) -> Self {
+                    Self {
+                        config: config.clone(),
+                        _config: PhantomData,
+                    }
+                }
+            }
+
+            impl<T: Config> DispatchNested<T::RuntimeOrigin, T::RuntimeCall> for Pallet<T> {}
+
+            impl<T: Config> crate::support::ValidateUnsigned for Pallet<T> {
+                type Call = Call<T>;
+            }
+        }
+
+        // The runtime finds the parts of each pallet in the module named after it.
+        mod first {
+            pub use super::recorder::*;
+        }
+        mod second {
+            pub use super::recorder::*;
+        }
+
+        #[derive(Debug, Clone)]
+        #[macros::runtime]
+        pub struct Runtime {
+            system: system::Pallet<Self>,
+            first: first::Pallet<Self>,
+            second: second::Pallet<Self>,
+        }
+        impl system::Config for Runtime {
+            type AccountId = types::AccountID;
+            type BlockNumber = types::BlockNumber;
+            type Nonce = types::Nonce;
+            type RuntimeEvent = RuntimeEvent;
+            type RuntimeCall = RuntimeCall;
+            type RuntimeOrigin = RuntimeOrigin;
+            const MAX_BLOCK_WEIGHT: support::Weight = 1_000;
+        }
+        impl recorder::Config for Runtime {}
+
+        impl support::ChargeTransaction for Runtime {
+            type AccountId = types::AccountID;
+            type Call = RuntimeCall;
+
+            fn charge_transaction(
+                &mut self,
+                _who: &Self::AccountId,
+                _call: &Self::Call,
+                _len: usize,
+            ) -> support::DispatchResult {
+                Ok(())
+            }
+        }
+
+        #[test]
+        fn hooks_are_called_in_the_order_of_the_pallets() {
+            use recorder::{take_calls, Hook};
+
+            let genesis = RuntimeGenesisConfig {
+                first: recorder::GenesisConfig {
+                    name: "first".to_string(),
+                    idle_weight: 100,
+                },
+                second: recorder::GenesisConfig {
+                    name: "second".to_string(),
+                    idle_weight: 1_000,
+                },
+                ..Default::default()
+            };
+            let mut runtime = Runtime::from_genesis(&Storage::default(), &genesis);
+            let header = types::Header {
+                parent_hash: runtime.system.parent_hash(),
+                block_number: 1,
+                state_root: H256::default(),
+                extrinsics_root: support::merkle_root(&[]),
+                author: None,
+            };
+
+            runtime.initialize_block(&header);
+            assert_eq!(
+                take_calls(),
+                vec![
+                    ("first".to_string(), Hook::Initialize),
+                    ("second".to_string(), Hook::Initialize),
+                ]
+            );
+
+            // Each pallet is offered the weight the block and the pallets before it left.
+            runtime.finalize_block(300);
+            assert_eq!(
+                take_calls(),
+                vec![
+                    ("first".to_string(), Hook::Idle(700)),
+                    ("second".to_string(), Hook::Idle(600)),
+                    ("first".to_string(), Hook::Finalize),
+                    ("second".to_string(), Hook::Finalize),
+                ]
+            );
+        }
     }
 }
@@ -0,0 +1,687 @@
+use crate::builder::{self, BlockBuilder};
+use crate::chain::{self, BlockId, ChainStore, ForkChoice, LongestChain};
+use crate::pool::{self, TransactionPool};
+use crate::support::{
+    crypto::Public,
+    hash_of,
+    storage::{Changes, Storage},
+    DispatchError, H256,
+};
+use crate::ws::Subscriptions;
+use crate::{system, types, Runtime, RuntimeEvent, RuntimeGenesisConfig};
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File, OpenOptions, TryLockError},
+    io,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// The file holding the state of the runtime, inside the base path of a node.
+const STATE_FILE: &str = "state.log";
+/// The file holding every imported block, in order, inside the base path of a node.
+const BLOCKS_FILE: &str = "blocks.log";
+/// The file locked by the node which has the base path open.
+const LOCK_FILE: &str = "LOCK";
+
+/// The reasons a node can fail.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing the files of the node failed.
+    Io(io::Error),
+    /// The files of the node do not hold a valid chain for the given genesis.
+    Corrupt(&'static str),
+    /// The runtime rejected a block.
+    InvalidBlock(DispatchError),
+    /// The block was already imported.
+    KnownBlock,
+    /// The parent of the block is not known.
+    UnknownParent,
+    /// Another process has the chain in this base path open.
+    Locked(PathBuf),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Corrupt(reason) => write!(f, "corrupt chain data: {}", reason),
+            Error::InvalidBlock(e) => write!(f, "invalid block: {:?}", e),
+            Error::KnownBlock => write!(f, "block already imported"),
+            Error::UnknownParent => write!(f, "unknown parent block"),
+            Error::Locked(path) => write!(
+                f,
+                "the chain in {} is in use by another process",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<DispatchError> for Error {
+    fn from(e: DispatchError) -> Self {
+        Error::InvalidBlock(e)
+    }
+}
+
+impl From<chain::Error> for Error {
+    fn from(e: chain::Error) -> Self {
+        match e {
+            chain::Error::Io(e) => Error::Io(e),
+            chain::Error::Corrupt(reason) => Error::Corrupt(reason),
+        }
+    }
+}
+
+/// How a node authors and picks blocks.
+#[derive(Clone)]
+pub struct Config {
+    /// The time between two blocks authored by the node.
+    pub block_time: Duration,
+    /// The maximum encoded size of the extrinsics of an authored block.
+    pub max_block_size: usize,
+    /// The maximum number of transactions in the pool.
+    pub pool_size: usize,
+    /// The rule which picks the best chain when blocks compete.
+    pub fork_choice: Rc<dyn ForkChoice>,
+    /// The account named as the author of the blocks authored by the node, which is credited with
+    /// their transaction fees.
+    pub author: Option<Public>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            block_time: Duration::from_secs(1),
+            max_block_size: builder::DEFAULT_MAX_BLOCK_SIZE,
+            pool_size: pool::DEFAULT_POOL_SIZE,
+            fork_choice: Rc::new(LongestChain),
+            author: None,
+        }
+    }
+}
+
+/// A node runs the runtime, and keeps the chain on disk so it can be stopped and resumed.
+///
+/// The state of the runtime and the imported blocks are kept in two append-only logs, in the base
+/// path of the node. Every imported block is written to disk before the state it produces, so if
+/// the node is stopped in between, the block is executed again when the node resumes.
+///
+/// Blocks can build on any known block, so the node keeps every branch of the chain. Each block is
+/// kept with the changes which revert its state, so the node can move from one branch to another
+/// when the fork-choice rule picks a new best block.
+///
+/// The node also keeps a pool of the transactions submitted to it, which is not kept on disk, and
+/// authors blocks out of them.
+pub struct Node {
+    runtime: Runtime,
+    chain: ChainStore,
+    pool: TransactionPool,
+    config: Config,
+    /// When the next block is due.
+    next_slot: Instant,
+    subscriptions: Subscriptions,
+    /// The events deposited by the blocks executed during the current import.
+    events: HashMap<H256, Vec<system::EventRecord<RuntimeEvent>>>,
+    /// The lock on the base path, held as long as the node is open, unless it is read-only.
+    _lock: Option<File>,
+}
+
+impl Node {
+    /// Open the chain kept in `base_path`, or start a new chain from `genesis` if there is none.
+    ///
+    /// The chain resumes from its last imported block, with the same state as before it stopped.
+    /// The chain must have been started from the same `genesis`, and cannot be open in another
+    /// process.
+    pub fn open(
+        base_path: &Path,
+        genesis: &RuntimeGenesisConfig,
+        config: Config,
+    ) -> Result<Node, Error> {
+        fs::create_dir_all(base_path)?;
+        let lock = lock(base_path)?;
+        let storage = Storage::open(&base_path.join(STATE_FILE))?;
+        let chain = ChainStore::open(
+            &base_path.join(BLOCKS_FILE),
+            genesis_header(genesis),
+            config.fork_choice.clone(),
+        )?;
+        Node::resume(storage, chain, genesis, config, Some(lock))
+    }
+
+    /// Open the chain kept in `base_path` without ever writing to it, even while another process has
+    /// it open. The node works as usual, but nothing it does is written to disk.
+    pub fn open_read_only(
+        base_path: &Path,
+        genesis: &RuntimeGenesisConfig,
+        config: Config,
+    ) -> Result<Node, Error> {
+        let storage = Storage::open_read_only(&base_path.join(STATE_FILE))?;
+        let chain = ChainStore::open_read_only(
+            &base_path.join(BLOCKS_FILE),
+            genesis_header(genesis),
+            config.fork_choice.clone(),
+        )?;
+        Node::resume(storage, chain, genesis, config, None)
+    }
+
+    /// Resume the chain of `chain`, whose state after one of its blocks is in `storage`.
+    fn resume(
+        storage: Storage,
+        chain: ChainStore,
+        genesis: &RuntimeGenesisConfig,
+        config: Config,
+        lock: Option<File>,
+    ) -> Result<Node, Error> {
+        let mut runtime = if storage.is_empty() {
+            if chain.best_number() != chain.genesis_number() {
+                return Err(Error::Corrupt(
+                    "blocks were imported, but there is no state",
+                ));
+            }
+            Runtime::from_genesis(&storage, genesis)
+        } else {
+            let mut runtime = Runtime::from_storage(&storage);
+            runtime.system.set_genesis_hash(chain.genesis_hash());
+            runtime
+        };
+
+        // The state is the one of the last block whose state was written, which may not be the
+        // best block if the node stopped in the middle of an import.
+        let head = chain
+            .find(runtime.system.block_number(), storage.root())
+            .ok_or(Error::Corrupt(
+                "the state does not match any imported block",
+            ))?;
+        runtime.system.set_parent_hash(head);
+
+        let mut node = Node {
+            runtime,
+            chain,
+            pool: TransactionPool::new(config.pool_size),
+            next_slot: Instant::now() + config.block_time,
+            subscriptions: Subscriptions::default(),
+            events: HashMap::new(),
+            config,
+            _lock: lock,
+        };
+        node.set_head(node.chain.best_hash())?;
+        storage.flush()?;
+        Ok(node)
+    }
+
+    /// The runtime, holding the state after the last imported block.
+    pub fn runtime(&self) -> &Runtime {
+        &self.runtime
+    }
+
+    /// The imported blocks, up to the best block.
+    pub fn chain(&self) -> &ChainStore {
+        &self.chain
+    }
+
+    /// The pool of transactions waiting to be included in a block.
+    pub fn pool(&self) -> &TransactionPool {
+        &self.pool
+    }
+
+    /// Remove the chain kept in `base_path`, and the directory itself unless it holds other files.
+    /// Returns whether there was a chain to remove. The chain cannot be open in another process.
+    pub fn purge(base_path: &Path) -> Result<bool, Error> {
+        if !base_path.is_dir() {
+            return Ok(false);
+        }
+        let lock = lock(base_path)?;
+        let mut removed = false;
+        for file in [STATE_FILE, BLOCKS_FILE] {
+            let path = base_path.join(file);
+            // A log may have been left in the middle of a rewrite.
+            for path in [path.with_extension("tmp"), path] {
+                match fs::remove_file(&path) {
+                    Ok(()) => removed = true,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        fs::remove_file(base_path.join(LOCK_FILE))?;
+        drop(lock);
+        let _ = fs::remove_dir(base_path);
+        Ok(removed)
+    }
+
+    /// The subscriptions to the blocks imported by the node.
+    pub fn subscriptions(&mut self) -> &mut Subscriptions {
+        &mut self.subscriptions
+    }
+
+    /// Validate `extrinsic` against the state after the last imported block, and add it to the
+    /// pool. Returns the hash of the transaction.
+    pub fn submit_extrinsic(&mut self, extrinsic: types::Extrinsic) -> Result<H256, pool::Error> {
+        self.pool.submit(&self.runtime, extrinsic)
+    }
+
+    /// The nonce the next transaction of `who` should use, counting the ready transactions of
+    /// `who` in the pool.
+    pub fn next_nonce(&self, who: &types::AccountID) -> types::Nonce {
+        self.pool.next_nonce(who, self.runtime.system.nonce(who))
+    }
+
+    /// The hash of the block whose state the runtime holds, which is the best block outside of
+    /// an import.
+    fn head(&self) -> H256 {
+        self.runtime.system.parent_hash()
+    }
+
+    /// Execute `block` on top of the block whose state the runtime holds. Returns the changes which
+    /// revert the state to what it was before.
+    fn execute(&mut self, block: types::Block) -> Result<Changes, Error> {
+        let storage = self.runtime.system.storage().clone();
+        storage.start_transaction();
+        let hash = block.hash();
+        match self.runtime.execute_block(block) {
+            Ok(()) => {
+                let events = self.runtime.system.events().to_vec();
+                self.events.insert(hash, events);
+                Ok(storage.commit_transaction_with_undo())
+            }
+            Err(e) => {
+                storage.rollback_transaction();
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Move the runtime to the state after block `target`: revert the blocks of the current head
+    /// down to the latest common ancestor with `target`, then execute the blocks from there up to
+    /// `target`.
+    fn set_head(&mut self, target: H256) -> Result<(), Error> {
+        let (retracted, enacted) = self.chain.route(self.head(), target);
+        for hash in retracted {
+            let undo = self.chain.undo(&hash).expect("the route has known blocks");
+            self.runtime.system.storage().revert(undo);
+            let parent_hash = self.chain.header(BlockId::Hash(hash)).unwrap().parent_hash;
+            self.runtime.system.set_parent_hash(parent_hash);
+        }
+        for hash in enacted {
+            let block = self.chain.block(BlockId::Hash(hash)).unwrap().clone();
+            self.execute(block)?;
+        }
+        Ok(())
+    }
+
+    /// Execute `block` on top of its parent, which can be any known block, and write it to disk
+    /// along with the changes it makes to the state of its parent. A block which the runtime
+    /// rejects is not written.
+    ///
+    /// The runtime then moves to the best block picked by the fork-choice rule, reverting and
+    /// executing blocks as needed, and the pool is updated for the new state. Subscribers are
+    /// notified of every block which joined the best chain.
+    pub fn import_block(&mut self, block: types::Block) -> Result<(), Error> {
+        if self.chain.contains(&block.hash()) {
+            return Err(Error::KnownBlock);
+        }
+        if !self.chain.contains(&block.header.parent_hash) {
+            return Err(Error::UnknownParent);
+        }
+
+        self.events.clear();
+        let old_head = self.head();
+        self.set_head(block.header.parent_hash)?;
+        let undo = match self.execute(block.clone()) {
+            Ok(undo) => undo,
+            Err(e) => {
+                self.set_head(old_head)?;
+                return Err(e);
+            }
+        };
+        // The block is not known if it cannot be written, so its state is dropped as well.
+        let parent_hash = block.header.parent_hash;
+        if let Err(e) = self.chain.import(block, undo.clone()) {
+            self.runtime.system.storage().revert(&undo);
+            self.runtime.system.set_parent_hash(parent_hash);
+            self.set_head(old_head)?;
+            return Err(e.into());
+        }
+        self.set_head(self.chain.best_hash())?;
+        self.runtime.system.storage().flush()?;
+
+        let (retracted, enacted) = self.chain.route(old_head, self.head());
+        let blocks = |hashes: Vec<H256>| {
+            hashes
+                .iter()
+                .map(|hash| self.chain.block(BlockId::Hash(*hash)).unwrap().clone())
+                .collect::<Vec<_>>()
+        };
+        let (enacted, retracted) = (blocks(enacted), blocks(retracted));
+        self.pool.maintain(&self.runtime, &enacted, &retracted);
+        for block in &enacted {
+            let events = self.events.remove(&block.hash()).unwrap_or_default();
+            self.subscriptions.notify_block(&block.header, &events);
+        }
+        Ok(())
+    }
+
+    /// Build a block on top of the last imported block out of the ready transactions of the pool,
+    /// and import it. Transactions which turn out to be invalid are removed from the pool, and
+    /// transactions which do not fit in the block are left for the next one.
+    ///
+    /// The next block is then due `block_time` after this one was due.
+    pub fn author_block(&mut self) -> Result<types::Block, Error> {
+        let now = Instant::now();
+        self.next_slot += self.config.block_time;
+        // A node which fell behind does not author a burst of blocks to catch up.
+        if self.next_slot < now {
+            self.next_slot = now + self.config.block_time;
+        }
+        let mut builder = BlockBuilder::new(
+            &self.runtime,
+            self.config.author,
+            self.config.max_block_size,
+        );
+        let mut invalid_signers = Vec::new();
+        for extrinsic in self.pool.ready() {
+            // The next transactions of a signer cannot be included after an invalid one, but they
+            // stay in the pool.
+            let signer = extrinsic
+                .signature
+                .as_ref()
+                .map(|signature| signature.signer);
+            if signer.is_some_and(|signer| invalid_signers.contains(&signer)) {
+                continue;
+            }
+            let hash = hash_of(&extrinsic);
+            match builder.push(extrinsic) {
+                Ok(_) => {}
+                Err(builder::Error::Invalid(_)) => {
+                    self.pool.remove(&hash);
+                    invalid_signers.extend(signer);
+                }
+                Err(builder::Error::ExhaustsResources) => break,
+            }
+        }
+        let block = builder.build();
+        self.import_block(block.clone())?;
+        Ok(block)
+    }
+
+    /// When the next block is due. Blocks are due every `block_time` of the config, however long
+    /// authoring them takes, unless the node falls behind.
+    pub fn next_block_due(&self) -> Instant {
+        self.next_slot
+    }
+}
+
+/// The header of the genesis block built from `genesis`. The genesis is not stored, since it can be
+/// built again from its configuration.
+fn genesis_header(genesis: &RuntimeGenesisConfig) -> types::Header {
+    Runtime::from_genesis(&Storage::default(), genesis).genesis_header()
+}
+
+/// Lock `base_path` for this process. The lock is released when the returned file is closed, which
+/// the system also does if the process dies.
+fn lock(base_path: &Path) -> Result<File, Error> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(base_path.join(LOCK_FILE))?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => Err(Error::Locked(base_path.to_path_buf())),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::support::{crypto::Pair, db::test_dir};
+    use crate::tests::{account, test_genesis, transfer, transfer_block};
+
+    #[test]
+    fn node_resumes_after_restart() {
+        let dir = test_dir("node-restart");
+        let genesis = test_genesis();
+
+        let mut node = Node::open(&dir, &genesis, Config::default()).unwrap();
+        for _ in 0..3 {
+            let block = transfer_block(node.runtime(), "Bob", 30);
+            node.import_block(block).unwrap();
+        }
+        // A block which is rejected is not written.
+        let block = transfer_block(node.runtime(), "Bob", 30);
+        let mut invalid = block.clone();
+        invalid.header.state_root = H256::default();
+        assert!(matches!(
+            node.import_block(invalid),
+            Err(Error::InvalidBlock(_))
+        ));
+        node.import_block(block.clone()).unwrap();
+        assert!(matches!(node.import_block(block), Err(Error::KnownBlock)));
+        let state_root = node.runtime().state_root();
+        let parent_hash = node.runtime().system.parent_hash();
+        drop(node);
+
+        let mut node = Node::open(&dir, &genesis, Config::default()).unwrap();
+        assert_eq!(node.runtime().system.block_number(), 4);
+        assert_eq!(node.runtime().state_root(), state_root);
+        assert_eq!(node.runtime().system.parent_hash(), parent_hash);
+        assert_eq!(node.runtime().balances.get_balance(&account("Bob")), 120);
+
+        // The chain goes on where it stopped.
+        let block = transfer_block(node.runtime(), "Bob", 30);
+        node.import_block(block).unwrap();
+        drop(node);
+
+        // Another genesis is another chain.
+        let mut other = genesis.clone();
+        other.system.block_number = 10;
+        assert!(matches!(
+            Node::open(&dir, &other, Config::default()),
+            Err(Error::Corrupt(_))
+        ));
+
+        // Once purged, the chain starts again from its genesis.
+        assert_eq!(Node::purge(&dir).ok(), Some(true));
+        assert!(!dir.exists());
+        assert_eq!(Node::purge(&dir).ok(), Some(false));
+        let node = Node::open(&dir, &genesis, Config::default()).unwrap();
+        assert_eq!(node.runtime().system.block_number(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn blocks_are_executed_again_after_a_crash() {
+        let dir = test_dir("node-crash");
+        let genesis = test_genesis();
+
+        let mut node = Node::open(&dir, &genesis, Config::default()).unwrap();
+        let block = transfer_block(node.runtime(), "Bob", 30);
+        node.import_block(block).unwrap();
+        let block = transfer_block(node.runtime(), "Bob", 30);
+        let state_root = block.header.state_root;
+
+        // The node stops after writing the block, but before writing its state.
+        let undo = node.execute(block.clone()).unwrap();
+        node.chain.import(block.clone(), undo).unwrap();
+        drop(node);
+
+        let node = Node::open(&dir, &genesis, Config::default()).unwrap();
+        assert_eq!(node.runtime().system.block_number(), 2);
+        assert_eq!(node.runtime().state_root(), state_root);
+        assert_eq!(node.runtime().system.parent_hash(), block.hash());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_block_which_cannot_be_written_is_not_imported() {
+        let dir = test_dir("node-write-error");
+        let mut node = Node::open(&dir, &test_genesis(), Config::default()).unwrap();
+        let genesis_hash = node.chain().best_hash();
+        let state_root = node.runtime().state_root();
+        let block = transfer_block(node.runtime(), "Bob", 30);
+
+        node.chain.set_writable(false);
+        assert!(matches!(
+            node.import_block(block.clone()),
+            Err(Error::Io(_))
+        ));
+        assert!(!node.chain().contains(&block.hash()));
+        assert_eq!(node.runtime().system.parent_hash(), genesis_hash);
+        assert_eq!(node.runtime().state_root(), state_root);
+
+        // The node carries on from its last block once the disk works again.
+        node.chain.set_writable(true);
+        node.import_block(block).unwrap();
+        let block = transfer_block(node.runtime(), "Bob", 30);
+        node.import_block(block.clone()).unwrap();
+        assert_eq!(node.runtime().system.parent_hash(), block.hash());
+        assert_eq!(node.runtime().balances.get_balance(&account("Bob")), 60);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_single_node_writes_to_a_chain() {
+        let dir = test_dir("node-lock");
+        let genesis = test_genesis();
+
+        let mut node = Node::open(&dir, &genesis, Config::default()).unwrap();
+        let block = transfer_block(node.runtime(), "Bob", 30);
+        node.import_block(block).unwrap();
+        // The block is written, but not its state yet.
+        let block = transfer_block(node.runtime(), "Bob", 30);
+        let undo = node.execute(block.clone()).unwrap();
+        node.chain.import(block.clone(), undo).unwrap();
+
+        assert!(matches!(
+            Node::open(&dir, &genesis, Config::default()),
+            Err(Error::Locked(_))
+        ));
+        assert!(matches!(Node::purge(&dir), Err(Error::Locked(_))));
+
+        // A read-only node executes the block again, but does not write its state.
+        let files = || [STATE_FILE, BLOCKS_FILE].map(|file| fs::read(dir.join(file)).unwrap());
+        let written = files();
+        let read_only = Node::open_read_only(&dir, &genesis, Config::default()).unwrap();
+        assert_eq!(read_only.runtime().system.parent_hash(), block.hash());
+        assert_eq!(files(), written);
+
+        drop(node);
+        let node = Node::open(&dir, &genesis, Config::default()).unwrap();
+        assert_eq!(node.runtime().system.parent_hash(), block.hash());
+        drop(node);
+        assert!(Node::purge(&dir).unwrap());
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn authored_blocks_include_the_ready_transactions() {
+        let dir = test_dir("node-author");
+        let genesis = test_genesis();
+        let config = Config {
+            author: Some(account("Dave")),
+            ..Default::default()
+        };
+
+        let mut node = Node::open(&dir, &genesis, config).unwrap();
+        let first = transfer(node.runtime(), "Bob", 30);
+        let second = types::Extrinsic::new_signed(
+            transfer(node.runtime(), "Charlie", 10).call,
+            1,
+            &Pair::from_name("Alice"),
+            node.runtime().system.genesis_hash(),
+        );
+        // The pool orders the transactions of Alice by nonce.
+        node.submit_extrinsic(second).unwrap();
+        node.submit_extrinsic(first).unwrap();
+
+        let block = node.author_block().unwrap();
+        assert_eq!(block.extrinsics.len(), 2);
+        assert_eq!(node.runtime().system.parent_hash(), block.hash());
+        assert_eq!(node.runtime().balances.get_balance(&account("Bob")), 30);
+        assert_eq!(node.runtime().balances.get_balance(&account("Charlie")), 10);
+        assert_eq!(node.pool().status().ready, 0);
+        // The author of the block is paid the fees of its transactions.
+        assert_eq!(block.header.author, Some(account("Dave")));
+        let fees = 1_000_000 - 40 - node.runtime().balances.get_balance(&account("Alice"));
+        assert!(fees > 0);
+        assert_eq!(node.runtime().balances.get_balance(&account("Dave")), fees);
+
+        // An empty pool makes an empty block.
+        let block = node.author_block().unwrap();
+        assert!(block.extrinsics.is_empty());
+        assert_eq!(node.runtime().system.block_number(), 2);
+        drop(node);
+
+        let node = Node::open(&dir, &genesis, Config::default()).unwrap();
+        assert_eq!(node.runtime().system.parent_hash(), block.hash());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn node_reorganizes_to_the_best_chain() {
+        let genesis = test_genesis();
+        // Another node builds a competing chain from the same genesis.
+        let other_dir = test_dir("node-fork-other");
+        let mut other = Node::open(&other_dir, &genesis, Config::default()).unwrap();
+        let b1 = transfer_block(other.runtime(), "Charlie", 10);
+        other.import_block(b1.clone()).unwrap();
+        let b2 = transfer_block(other.runtime(), "Charlie", 10);
+        other.import_block(b2.clone()).unwrap();
+
+        let dir = test_dir("node-fork");
+        let mut node = Node::open(&dir, &genesis, Config::default()).unwrap();
+        let a1 = transfer_block(node.runtime(), "Bob", 30);
+        node.import_block(a1.clone()).unwrap();
+        // A transaction waiting in the pool, with the next nonce of Alice on top of `a1`.
+        let pending = transfer(node.runtime(), "Dave", 5);
+        node.submit_extrinsic(pending.clone()).unwrap();
+
+        // Of two chains of the same length, the one whose last block has the lowest hash wins.
+        node.import_block(b1.clone()).unwrap();
+        let best = if a1.hash() < b1.hash() { &a1 } else { &b1 };
+        assert_eq!(node.chain().best_hash(), best.hash());
+        assert_eq!(node.runtime().system.parent_hash(), best.hash());
+
+        // A longer chain wins, and the state is the state of its last block.
+        node.import_block(b2.clone()).unwrap();
+        assert_eq!(node.chain().best_hash(), b2.hash());
+        assert_eq!(node.runtime().state_root(), b2.header.state_root);
+        assert_eq!(node.runtime().balances.get_balance(&account("Bob")), 0);
+        assert_eq!(node.runtime().balances.get_balance(&account("Charlie")), 20);
+        assert_eq!(node.runtime().system.nonce(&account("Alice")), 2);
+        // The transaction of `a1` and the pending one both used nonces of Alice which the new chain
+        // used as well.
+        assert_eq!(node.pool().status().ready, 0);
+        let b3 = transfer_block(other.runtime(), "Bob", 1);
+        node.import_block(b3).unwrap();
+        assert_eq!(node.chain().best_number(), 3);
+
+        // A block on a parent nobody has seen cannot be imported.
+        let mut orphan = transfer_block(node.runtime(), "Bob", 1);
+        orphan.header.parent_hash = H256::default();
+        assert!(matches!(
+            node.import_block(orphan),
+            Err(Error::UnknownParent)
+        ));
+        let best = node.chain().best_hash();
+        let state_root = node.runtime().state_root();
+        drop(node);
+
+        let node = Node::open(&dir, &genesis, Config::default()).unwrap();
+        assert_eq!(node.chain().best_hash(), best);
+        assert_eq!(node.runtime().state_root(), state_root);
+        assert!(node.chain().block(BlockId::Hash(a1.hash())).is_some());
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(other_dir).unwrap();
+    }
+}
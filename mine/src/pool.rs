@@ -0,0 +1,589 @@
+use crate::support::{codec::Encode, hash_of, DispatchError, GetWeight, ValidateUnsigned, H256};
+use crate::{balances, types, Runtime};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap},
+    fmt,
+};
+
+/// The number of transactions a pool holds by default.
+pub const DEFAULT_POOL_SIZE: usize = 1_000;
+
+/// The reasons a transaction is not accepted by the pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The signature does not match the signer and the content of the transaction.
+    BadSignature,
+    /// The nonce of the transaction was already used by its signer.
+    Stale,
+    /// The signer does not have enough tokens to pay the fee of the transaction.
+    CannotPayFee,
+    /// No pallet accepts the call of this unsigned transaction.
+    InvalidUnsigned(DispatchError),
+    /// The transaction is already in the pool.
+    AlreadyImported,
+    /// The pool holds a transaction with the same signer and nonce, and a higher priority.
+    TooLowPriority,
+    /// The pool is full of transactions with a higher priority.
+    PoolFull,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::BadSignature => write!(f, "bad signature"),
+            Error::Stale => write!(f, "nonce already used"),
+            Error::CannotPayFee => write!(f, "signer cannot pay the transaction fee"),
+            Error::InvalidUnsigned(e) => write!(f, "invalid unsigned transaction: {:?}", e),
+            Error::AlreadyImported => write!(f, "transaction already in the pool"),
+            Error::TooLowPriority => write!(f, "a transaction with the same nonce has priority"),
+            Error::PoolFull => write!(f, "transaction pool is full"),
+        }
+    }
+}
+
+/// The number of transactions in the pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStatus {
+    /// The transactions which can be included in the next block.
+    pub ready: usize,
+    /// The transactions waiting for a transaction with a lower nonce of the same signer.
+    pub future: usize,
+}
+
+/// A transaction which was validated by the pool.
+#[derive(Debug, Clone)]
+struct PoolTransaction {
+    extrinsic: types::Extrinsic,
+    /// Transactions with a higher priority are included first.
+    priority: u128,
+    /// The order in which transactions entered the pool, which breaks ties between priorities.
+    insertion: u64,
+}
+
+impl PoolTransaction {
+    /// How this transaction ranks against others, the greatest being the best.
+    fn rank(&self) -> (u128, Reverse<u64>) {
+        (self.priority, Reverse(self.insertion))
+    }
+}
+
+/// The transactions sent by one account, by nonce.
+#[derive(Debug, Clone, Default)]
+struct Sender {
+    /// The nonce of the account in the state the pool last validated against.
+    nonce: types::Nonce,
+    transactions: BTreeMap<types::Nonce, H256>,
+    /// The fees of all the transactions, which the account must be able to pay together.
+    fees: u128,
+}
+
+impl Sender {
+    /// The transactions which are ready: those whose nonces follow each other from the nonce of
+    /// the account.
+    fn ready(&self) -> impl Iterator<Item = &H256> {
+        self.transactions
+            .iter()
+            .zip(self.nonce..)
+            .take_while(|((nonce, _), expected)| **nonce == *expected)
+            .map(|((_, hash), _)| hash)
+    }
+}
+
+/// A pool of transactions submitted to the node, waiting to be included in a block.
+///
+/// Every transaction is validated against the current state when it is submitted. A signed
+/// transaction whose nonce is ahead of the nonce of its signer is kept as a "future" transaction,
+/// until the transactions with the nonces before it are included. The pool is maintained after
+/// every imported block, which removes the included and stale transactions.
+///
+/// The priority of a signed transaction is its fee. Unsigned transactions pay no fee, so they have
+/// the lowest priority.
+#[derive(Debug, Clone)]
+pub struct TransactionPool {
+    transactions: BTreeMap<H256, PoolTransaction>,
+    senders: BTreeMap<types::AccountID, Sender>,
+    /// The maximum number of transactions in the pool.
+    max_size: usize,
+    insertions: u64,
+}
+
+impl TransactionPool {
+    /// Create an empty pool, holding at most `max_size` transactions.
+    pub fn new(max_size: usize) -> Self {
+        TransactionPool {
+            transactions: BTreeMap::new(),
+            senders: BTreeMap::new(),
+            max_size,
+            insertions: 0,
+        }
+    }
+
+    /// Validate `extrinsic` against the state of `runtime`, and add it to the pool.
+    /// Returns the hash of the transaction.
+    pub fn submit(
+        &mut self,
+        runtime: &Runtime,
+        extrinsic: types::Extrinsic,
+    ) -> Result<H256, Error> {
+        let hash = hash_of(&extrinsic);
+        if self.transactions.contains_key(&hash) {
+            return Err(Error::AlreadyImported);
+        }
+        let priority = validate(runtime, &extrinsic)?;
+
+        // A transaction can replace another one with the same signer and nonce, if it pays more.
+        let replaced = match &extrinsic.signature {
+            Some(signature) => self
+                .senders
+                .get(&signature.signer)
+                .and_then(|sender| sender.transactions.get(&signature.nonce))
+                .copied(),
+            None => None,
+        };
+        let replaced_fee = replaced.map_or(0, |replaced| self.transactions[&replaced].priority);
+        if replaced.is_some() && replaced_fee >= priority {
+            return Err(Error::TooLowPriority);
+        }
+        if let Some(signature) = &extrinsic.signature {
+            let pending = self
+                .senders
+                .get(&signature.signer)
+                .map_or(0, |sender| sender.fees);
+            let fees = (pending - replaced_fee).saturating_add(priority);
+            if fees > runtime.balances.get_balance(&signature.signer) {
+                return Err(Error::CannotPayFee);
+            }
+        }
+        if let Some(replaced) = replaced {
+            self.remove(&replaced);
+        }
+
+        // When the pool is full, the future transactions are evicted first, then the ready
+        // transactions with the lowest priority.
+        if self.transactions.len() >= self.max_size {
+            let (worst, worst_is_future) = self
+                .transactions
+                .iter()
+                .map(|(hash, transaction)| (hash, self.is_future(hash), transaction.rank()))
+                .min_by_key(|(_, is_future, rank)| (!is_future, *rank))
+                .map(|(hash, is_future, _)| (*hash, is_future))
+                .expect("the pool is full");
+            let is_future = extrinsic.signature.as_ref().is_some_and(|signature| {
+                let chain_nonce = runtime.system.nonce(&signature.signer);
+                signature.nonce != self.next_nonce(&signature.signer, chain_nonce)
+            });
+            let evicts =
+                (worst_is_future && !is_future) || self.transactions[&worst].priority < priority;
+            if !evicts {
+                return Err(Error::PoolFull);
+            }
+            self.remove(&worst);
+        }
+
+        if let Some(signature) = &extrinsic.signature {
+            let sender = self.senders.entry(signature.signer).or_default();
+            sender.nonce = runtime.system.nonce(&signature.signer);
+            sender.transactions.insert(signature.nonce, hash);
+            sender.fees += priority;
+        }
+        self.insertions += 1;
+        self.transactions.insert(
+            hash,
+            PoolTransaction {
+                extrinsic,
+                priority,
+                insertion: self.insertions,
+            },
+        );
+        Ok(hash)
+    }
+
+    /// Remove the transaction with `hash`, if it is in the pool.
+    pub fn remove(&mut self, hash: &H256) -> Option<types::Extrinsic> {
+        let transaction = self.transactions.remove(hash)?;
+        if let Some(signature) = &transaction.extrinsic.signature {
+            let sender = self
+                .senders
+                .get_mut(&signature.signer)
+                .expect("every signed transaction has a sender");
+            sender.transactions.remove(&signature.nonce);
+            sender.fees -= transaction.priority;
+            if sender.transactions.is_empty() {
+                self.senders.remove(&signature.signer);
+            }
+        }
+        Some(transaction.extrinsic)
+    }
+
+    /// Whether the transaction with `hash` waits for another transaction of its signer.
+    fn is_future(&self, hash: &H256) -> bool {
+        match &self.transactions[hash].extrinsic.signature {
+            Some(signature) => !self.senders[&signature.signer]
+                .ready()
+                .any(|ready| ready == hash),
+            None => false,
+        }
+    }
+
+    /// The ready transactions, in the order they should be included in a block: best priority
+    /// first, while the transactions of each signer stay in nonce order.
+    pub fn ready(&self) -> Vec<types::Extrinsic> {
+        // The candidates are the unsigned transactions, and the next ready transaction of every
+        // signer. Once a transaction is taken, the next one of its signer becomes a candidate.
+        let mut ready = self
+            .senders
+            .values()
+            .map(|sender| sender.ready().copied().collect::<Vec<_>>().into_iter())
+            .collect::<Vec<_>>();
+        let mut candidates = BinaryHeap::new();
+        for (index, transactions) in ready.iter_mut().enumerate() {
+            if let Some(hash) = transactions.next() {
+                candidates.push((self.transactions[&hash].rank(), hash, Some(index)));
+            }
+        }
+        for (hash, transaction) in &self.transactions {
+            if transaction.extrinsic.signature.is_none() {
+                candidates.push((transaction.rank(), *hash, None));
+            }
+        }
+
+        let mut extrinsics = Vec::new();
+        while let Some((_, hash, index)) = candidates.pop() {
+            extrinsics.push(self.transactions[&hash].extrinsic.clone());
+            if let Some(next) = index.and_then(|index| ready[index].next()) {
+                candidates.push((self.transactions[&next].rank(), next, index));
+            }
+        }
+        extrinsics
+    }
+
+    /// The nonce the next transaction of `who` should use, after the ready transactions of `who`
+    /// in the pool. `chain_nonce` is the nonce of `who` in the current state.
+    pub fn next_nonce(&self, who: &types::AccountID, chain_nonce: types::Nonce) -> types::Nonce {
+        match self.senders.get(who) {
+            Some(sender) if sender.nonce == chain_nonce => {
+                chain_nonce + sender.ready().count() as types::Nonce
+            }
+            _ => chain_nonce,
+        }
+    }
+
+    /// The number of ready and future transactions in the pool.
+    pub fn status(&self) -> PoolStatus {
+        let ready = self.ready().len();
+        PoolStatus {
+            ready,
+            future: self.transactions.len() - ready,
+        }
+    }
+
+    /// Update the pool after the best block changed, where `runtime` holds the state after the
+    /// new best block. `enacted` are the blocks which joined the best chain, and `retracted` the
+    /// blocks which left it in a reorganization.
+    ///
+    /// The transactions included in `enacted` are removed, and every other transaction is
+    /// validated again against the new state. Transactions which are no longer valid, such as those
+    /// whose nonce was used in the meantime, are evicted. The transactions of `retracted` are then
+    /// submitted again, so they can be included in the new best chain.
+    pub fn maintain(
+        &mut self,
+        runtime: &Runtime,
+        enacted: &[types::Block],
+        retracted: &[types::Block],
+    ) {
+        for extrinsic in enacted.iter().flat_map(|block| &block.extrinsics) {
+            self.remove(&hash_of(extrinsic));
+        }
+
+        let invalid = self
+            .transactions
+            .iter()
+            .filter(|(_, transaction)| validate(runtime, &transaction.extrinsic).is_err())
+            .map(|(hash, _)| *hash)
+            .collect::<Vec<_>>();
+        for hash in invalid {
+            self.remove(&hash);
+        }
+        for (who, sender) in self.senders.iter_mut() {
+            sender.nonce = runtime.system.nonce(who);
+        }
+        // The new state may leave a sender unable to pay for all of its transactions, in which
+        // case those with the highest nonces are evicted.
+        let transactions = &self.transactions;
+        let unpayable = self
+            .senders
+            .iter()
+            .flat_map(|(who, sender)| {
+                let balance = runtime.balances.get_balance(who);
+                let mut fees = sender.fees;
+                sender.transactions.values().rev().take_while(move |hash| {
+                    let evicted = fees > balance;
+                    fees -= transactions[*hash].priority;
+                    evicted
+                })
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        for hash in unpayable {
+            self.remove(&hash);
+        }
+
+        for extrinsic in retracted.iter().flat_map(|block| &block.extrinsics) {
+            let _ = self.submit(runtime, extrinsic.clone());
+        }
+    }
+}
+
+/// Check whether `extrinsic` can be included in a block on top of the state of `runtime`, in which
+/// case it returns the priority of the transaction. The nonce of a signed transaction may be ahead
+/// of the nonce of its signer.
+fn validate(runtime: &Runtime, extrinsic: &types::Extrinsic) -> Result<u128, Error> {
+    let Some(signature) = &extrinsic.signature else {
+        runtime
+            .validate_unsigned(&extrinsic.call)
+            .map_err(Error::InvalidUnsigned)?;
+        return Ok(0);
+    };
+    if !extrinsic.verify(runtime.system.genesis_hash()) {
+        return Err(Error::BadSignature);
+    }
+    if signature.nonce < runtime.system.nonce(&signature.signer) {
+        return Err(Error::Stale);
+    }
+    let fee =
+        balances::Pallet::<Runtime>::compute_fee(extrinsic.call.weight(), extrinsic.encode().len())
+            .ok_or(Error::CannotPayFee)?;
+    if fee > runtime.balances.get_balance(&signature.signer) {
+        return Err(Error::CannotPayFee);
+    }
+    Ok(fee)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::support::{crypto::Pair, storage::Storage};
+    use crate::tests::{account, seal_block, test_genesis};
+    use crate::{proof_of_existance, RuntimeCall};
+
+    fn new_runtime() -> Runtime {
+        Runtime::from_genesis(&Storage::default(), &test_genesis())
+    }
+
+    fn transfer(runtime: &Runtime, from: &str, nonce: types::Nonce) -> types::Extrinsic {
+        types::Extrinsic::new_signed(
+            RuntimeCall::balances(balances::Call::transfer {
+                to: account("Bob"),
+                amount: 10,
+            }),
+            nonce,
+            &Pair::from_name(from),
+            runtime.system.genesis_hash(),
+        )
+    }
+
+    fn claim(runtime: &Runtime, from: &str, nonce: types::Nonce) -> types::Extrinsic {
+        types::Extrinsic::new_signed(
+            RuntimeCall::proof_of_existance(proof_of_existance::Call::create_claim {
+                claim: format!("claim {}", nonce),
+            }),
+            nonce,
+            &Pair::from_name(from),
+            runtime.system.genesis_hash(),
+        )
+    }
+
+    #[test]
+    fn transactions_are_validated() {
+        let runtime = new_runtime();
+        let mut pool = TransactionPool::new(DEFAULT_POOL_SIZE);
+
+        let mut forged = transfer(&runtime, "Alice", 0);
+        forged.signature.as_mut().unwrap().nonce = 1;
+        assert_eq!(pool.submit(&runtime, forged), Err(Error::BadSignature));
+        assert_eq!(
+            pool.submit(&runtime, transfer(&runtime, "Bob", 0)),
+            Err(Error::CannotPayFee)
+        );
+        let unsigned = types::Extrinsic {
+            signature: None,
+            call: transfer(&runtime, "Alice", 0).call,
+        };
+        assert_eq!(
+            pool.submit(&runtime, unsigned),
+            Err(Error::InvalidUnsigned(DispatchError::BadOrigin))
+        );
+
+        assert!(pool
+            .submit(&runtime, transfer(&runtime, "Alice", 0))
+            .is_ok());
+        assert_eq!(
+            pool.submit(&runtime, transfer(&runtime, "Alice", 0)),
+            Err(Error::AlreadyImported)
+        );
+        assert_eq!(
+            pool.status(),
+            PoolStatus {
+                ready: 1,
+                future: 0
+            }
+        );
+    }
+
+    #[test]
+    fn ready_transactions_are_ordered_by_priority_and_nonce() {
+        let mut runtime = new_runtime();
+        let block = seal_block(
+            &runtime,
+            vec![types::Extrinsic::new_signed(
+                RuntimeCall::balances(balances::Call::transfer {
+                    to: account("Charlie"),
+                    amount: 10_000,
+                }),
+                0,
+                &Pair::from_name("Alice"),
+                runtime.system.genesis_hash(),
+            )],
+        );
+        runtime.execute_block(block).unwrap();
+        let mut pool = TransactionPool::new(DEFAULT_POOL_SIZE);
+
+        // A claim weighs less than a transfer, so it pays a lower fee.
+        let claim = claim(&runtime, "Alice", 1);
+        let first = transfer(&runtime, "Alice", 2);
+        let second = transfer(&runtime, "Charlie", 0);
+        pool.submit(&runtime, first.clone()).unwrap();
+        assert_eq!(
+            pool.status(),
+            PoolStatus {
+                ready: 0,
+                future: 1
+            }
+        );
+        assert_eq!(pool.next_nonce(&account("Alice"), 1), 1);
+        pool.submit(&runtime, second.clone()).unwrap();
+        pool.submit(&runtime, claim.clone()).unwrap();
+        assert_eq!(
+            pool.status(),
+            PoolStatus {
+                ready: 3,
+                future: 0
+            }
+        );
+        assert_eq!(pool.next_nonce(&account("Alice"), 1), 3);
+
+        // The claim comes before the transfer of Alice, since it has a lower nonce.
+        let ready = pool.ready();
+        assert_eq!(
+            ready.iter().map(hash_of).collect::<Vec<_>>(),
+            vec![hash_of(&second), hash_of(&claim), hash_of(&first)]
+        );
+    }
+
+    #[test]
+    fn fees_of_a_sender_are_paid_together() {
+        let mut runtime = new_runtime();
+        let first = transfer(&runtime, "Charlie", 0);
+        let fee =
+            balances::Pallet::<Runtime>::compute_fee(first.call.weight(), first.encode().len())
+                .unwrap();
+        let block = seal_block(
+            &runtime,
+            vec![types::Extrinsic::new_signed(
+                RuntimeCall::balances(balances::Call::transfer {
+                    to: account("Charlie"),
+                    amount: fee + fee / 2,
+                }),
+                0,
+                &Pair::from_name("Alice"),
+                runtime.system.genesis_hash(),
+            )],
+        );
+        runtime.execute_block(block).unwrap();
+        let mut pool = TransactionPool::new(DEFAULT_POOL_SIZE);
+
+        // Charlie can pay the fee of either transfer, but not of both.
+        let second = transfer(&runtime, "Charlie", 1);
+        assert_eq!(validate(&runtime, &second), Ok(fee));
+        let first = pool.submit(&runtime, first).unwrap();
+        assert_eq!(
+            pool.submit(&runtime, second.clone()),
+            Err(Error::CannotPayFee)
+        );
+        pool.remove(&first);
+        assert!(pool.submit(&runtime, second).is_ok());
+    }
+
+    #[test]
+    fn pool_is_maintained_after_each_block() {
+        let mut runtime = new_runtime();
+        let mut pool = TransactionPool::new(DEFAULT_POOL_SIZE);
+        let included = transfer(&runtime, "Alice", 0);
+        let stale = claim(&runtime, "Alice", 1);
+        let future = transfer(&runtime, "Alice", 2);
+        pool.submit(&runtime, included.clone()).unwrap();
+        pool.submit(&runtime, stale).unwrap();
+        pool.submit(&runtime, future.clone()).unwrap();
+        assert_eq!(
+            pool.status(),
+            PoolStatus {
+                ready: 3,
+                future: 0
+            }
+        );
+
+        // Another transaction uses the nonce of the claim, so the claim becomes stale.
+        let block = seal_block(&runtime, vec![included, transfer(&runtime, "Alice", 1)]);
+        runtime.execute_block(block.clone()).unwrap();
+        pool.maintain(&runtime, &[block], &[]);
+        assert_eq!(
+            pool.status(),
+            PoolStatus {
+                ready: 1,
+                future: 0
+            }
+        );
+        assert_eq!(
+            pool.ready().iter().map(hash_of).collect::<Vec<_>>(),
+            vec![hash_of(&future)]
+        );
+        assert_eq!(
+            pool.submit(&runtime, transfer(&runtime, "Alice", 0)),
+            Err(Error::Stale)
+        );
+    }
+
+    #[test]
+    fn full_pool_evicts_lower_priority() {
+        let runtime = new_runtime();
+        let mut pool = TransactionPool::new(2);
+        pool.submit(&runtime, claim(&runtime, "Alice", 0)).unwrap();
+        let future = claim(&runtime, "Alice", 5);
+        pool.submit(&runtime, future.clone()).unwrap();
+
+        // A future transaction is evicted first, even with the same priority.
+        pool.submit(&runtime, claim(&runtime, "Alice", 1)).unwrap();
+        assert!(pool.remove(&hash_of(&future)).is_none());
+        assert_eq!(
+            pool.submit(&runtime, claim(&runtime, "Alice", 2)),
+            Err(Error::PoolFull)
+        );
+
+        // A transaction which pays more replaces the one with the same nonce.
+        assert!(pool
+            .submit(&runtime, transfer(&runtime, "Alice", 1))
+            .is_ok());
+        assert_eq!(
+            pool.submit(&runtime, claim(&runtime, "Alice", 1)),
+            Err(Error::TooLowPriority)
+        );
+        assert_eq!(
+            pool.status(),
+            PoolStatus {
+                ready: 2,
+                future: 0
+            }
+        );
+    }
+}
@@ -1,92 +1,286 @@
-use crate::support::DispatchResult;
+use crate::support::{
+    codec::{Decode, Encode},
+    storage::{Storage, StorageMap},
+    BuildGenesis, DispatchResult,
+};
+use crate::system::{ensure_root, ensure_signed};
 use core::fmt::Debug;
-use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
 
 pub trait Config: crate::system::Config {
     /// The type which represents the content that can be claimed using this pallet.
     /// Could be the content directly as bytes, or better yet the hash of that content.
     /// We leave that decision to the runtime developer.
-    type Content: Debug + Ord;
+    type Content: Debug + Ord + Clone + Encode + Decode;
+}
+
+/// Errors which can be returned by the Proof of Existence Module.
+#[macros::error]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The content has already been claimed by some account.
+    AlreadyClaimed,
+    /// There is no claim on the content.
+    NoSuchClaim,
+    /// The caller is not the owner of the claim.
+    NotClaimOwner,
+}
+
+/// Events deposited by the Proof of Existence Module.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(bound(serialize = "T::AccountId: Serialize, T::Content: Serialize"))]
+pub enum Event<T: Config> {
+    /// An account claimed some content.
+    ClaimCreated {
+        who: T::AccountId,
+        claim: T::Content,
+    },
+    /// A claim was revoked, by its owner or by root. `who` is the owner of the claim.
+    ClaimRevoked {
+        who: T::AccountId,
+        claim: T::Content,
+    },
+}
+
+/// The claims which already exist at genesis, as `(content, owner)` pairs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "T::Content: Serialize, T::AccountId: Serialize",
+    deserialize = "T::Content: Deserialize<'de>, T::AccountId: Deserialize<'de>"
+))]
+pub struct GenesisConfig<T: Config> {
+    pub claims: Vec<(T::Content, T::AccountId)>,
+}
+
+impl<T: Config> Default for GenesisConfig<T> {
+    fn default() -> Self {
+        GenesisConfig { claims: Vec::new() }
+    }
 }
 
 /// This is the Proof of Existence Module.
 /// It is a simple module that allows accounts to claim existence of some data.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Pallet<T: Config> {
     /// A simple storage map from content to the owner of that content.
     /// Accounts can make multiple different claims, but each claim can only have one owner.
-    claims: BTreeMap<T::Content, T::AccountId>,
+    claims: StorageMap<T::Content, T::AccountId>,
+    /// Events deposited since the runtime last collected them.
+    events: Vec<Event<T>>,
 }
 
 impl<T: Config> Pallet<T> {
-    /// Create a new instance of the Proof of Existence Module.
-    pub fn new() -> Self {
+    /// Create a new instance of the Proof of Existence Module, which keeps its state in `storage`.
+    pub fn new(storage: &Storage) -> Self {
         Self {
-            claims: BTreeMap::new(),
+            claims: StorageMap::new(storage, "ProofOfExistence", "Claims"),
+            events: Vec::new(),
         }
     }
 
+    /// Deposit an event, to be collected by the runtime.
+    pub fn deposit_event(&mut self, event: Event<T>) {
+        self.events.push(event);
+    }
+
+    /// Take all the events deposited since the last call.
+    pub fn take_events(&mut self) -> Vec<Event<T>> {
+        std::mem::take(&mut self.events)
+    }
+
     /// Get the owner (if any) of a claim.
-    pub fn get_claim(&self, claim: &T::Content) -> Option<&T::AccountId> {
+    pub fn get_claim(&self, claim: &T::Content) -> Option<T::AccountId> {
         self.claims.get(claim)
     }
 }
 
 #[macros::call]
 impl<T: Config> Pallet<T> {
-    /// Create a new claim on behalf of the `caller`.
+    /// Create a new claim on behalf of the signer of the `origin`.
     /// This function will return an error if someone already has claimed that content.
-    pub fn create_claim(&mut self, caller: T::AccountId, claim: T::Content) -> DispatchResult {
+    #[weight(50)]
+    pub fn create_claim(&mut self, origin: T::RuntimeOrigin, claim: T::Content) -> DispatchResult {
+        let caller = ensure_signed(origin)?;
         // check claim available
         if self.claims.contains_key(&claim) {
-            return Err(&"this content is already claimed");
+            return Err(Error::AlreadyClaimed.into());
         }
-        self.claims.insert(claim, caller);
+        self.claims.insert(&claim, &caller);
+        self.deposit_event(Event::ClaimCreated { who: caller, claim });
         Ok(())
     }
 
     /// Revoke an existing claim on some content.
     /// This function should only succeed if the caller is the owner of an existing claim.
     /// It will return an error if the claim does not exist, or if the caller is not the owner.
-    pub fn revoke_claim(&mut self, caller: T::AccountId, claim: T::Content) -> DispatchResult {
-        let owner = self.get_claim(&claim).ok_or("claim does not exist")?;
-        if owner != &caller {
-            return Err("caller is not the owner of the claim");
+    #[weight(50)]
+    pub fn revoke_claim(&mut self, origin: T::RuntimeOrigin, claim: T::Content) -> DispatchResult {
+        let caller = ensure_signed(origin)?;
+        let owner = self.get_claim(&claim).ok_or(Error::NoSuchClaim)?;
+        if owner != caller {
+            return Err(Error::NotClaimOwner.into());
         }
         self.claims.remove(&claim);
+        self.deposit_event(Event::ClaimRevoked { who: caller, claim });
+        Ok(())
+    }
+
+    /// Revoke an existing claim on some content, whoever its owner is.
+    /// Only root can do this. It will return an error if the claim does not exist.
+    #[weight(50)]
+    pub fn force_revoke_claim(
+        &mut self,
+        origin: T::RuntimeOrigin,
+        claim: T::Content,
+    ) -> DispatchResult {
+        ensure_root(origin)?;
+        let owner = self.claims.take(&claim).ok_or(Error::NoSuchClaim)?;
+        self.deposit_event(Event::ClaimRevoked { who: owner, claim });
         Ok(())
     }
 }
 
+impl<T: Config> BuildGenesis for Pallet<T> {
+    type GenesisConfig = GenesisConfig<T>;
+
+    fn build_genesis(storage: &Storage, config: &GenesisConfig<T>) -> Self {
+        let pallet = Self::new(storage);
+        for (claim, owner) in &config.claims {
+            pallet.claims.insert(claim, owner);
+        }
+        pallet
+    }
+}
+
+// This pallet never dispatches calls of other pallets.
+impl<T: Config> crate::support::DispatchNested<T::RuntimeOrigin, T::RuntimeCall> for Pallet<T> {}
+
+// This pallet does not accept any unsigned calls.
+impl<T: Config> crate::support::ValidateUnsigned for Pallet<T> {
+    type Call = Call<T>;
+}
+
 #[cfg(test)]
 mod test {
+    use super::{Error, Event, GenesisConfig};
+    use crate::support::{storage::Storage, BuildGenesis, DispatchError, H256};
+    use crate::system::RawOrigin;
+
+    #[derive(Debug, PartialEq)]
     struct TestConfig;
 
     impl super::Config for TestConfig {
-        type Content = &'static str;
+        type Content = String;
     }
 
     impl crate::system::Config for TestConfig {
-        type AccountId = &'static str;
+        type AccountId = String;
         type BlockNumber = u32;
         type Nonce = u32;
+        type RuntimeEvent = ();
+        type RuntimeCall = ();
+        type RuntimeOrigin = crate::system::RawOrigin<String>;
+        const MAX_BLOCK_WEIGHT: u64 = 1_000;
+    }
+
+    fn signed(who: &str) -> RawOrigin<String> {
+        RawOrigin::Signed(who.to_string())
+    }
+
+    #[test]
+    fn build_genesis() {
+        let pallet = super::Pallet::<TestConfig>::build_genesis(
+            &Storage::default(),
+            &GenesisConfig {
+                claims: vec![("hello".to_string(), "Alice".to_string())],
+            },
+        );
+        assert_eq!(
+            pallet.get_claim(&"hello".to_string()),
+            Some("Alice".to_string())
+        );
+        assert_eq!(pallet.get_claim(&"world".to_string()), None);
     }
 
     #[test]
     fn basic_proof_of_existence() {
-        let mut pallet: super::Pallet<TestConfig> = super::Pallet::<TestConfig>::new();
-        assert_eq!(pallet.get_claim(&"hello"), None);
-        assert_eq!(pallet.create_claim(&"Alice", "hello"), Ok(()));
-        assert_eq!(pallet.get_claim(&"hello"), Some(&"Alice"));
+        let storage = Storage::default();
+        let mut pallet: super::Pallet<TestConfig> = super::Pallet::<TestConfig>::new(&storage);
+        assert_eq!(pallet.get_claim(&"hello".to_string()), None);
+        assert_eq!(storage.root(), H256::default());
+        assert_eq!(
+            pallet.create_claim(signed("Alice"), "hello".to_string()),
+            Ok(())
+        );
+        assert_eq!(
+            pallet.get_claim(&"hello".to_string()),
+            Some("Alice".to_string())
+        );
+        assert_ne!(storage.root(), H256::default());
+        assert_eq!(
+            pallet.create_claim(signed("Bob"), "hello".to_string()),
+            Err(Error::AlreadyClaimed.into())
+        );
+        assert_eq!(
+            pallet.revoke_claim(signed("Bob"), "hello".to_string()),
+            Err(Error::NotClaimOwner.into())
+        );
+        assert_eq!(
+            pallet.revoke_claim(signed("Alice"), "hello".to_string()),
+            Ok(())
+        );
+        assert_eq!(pallet.get_claim(&"hello".to_string()), None);
+        assert_eq!(storage.root(), H256::default());
+        assert_eq!(
+            pallet.revoke_claim(signed("Alice"), "hello".to_string()),
+            Err(Error::NoSuchClaim.into())
+        );
+        assert_eq!(
+            pallet.take_events(),
+            vec![
+                Event::ClaimCreated {
+                    who: "Alice".to_string(),
+                    claim: "hello".to_string()
+                },
+                Event::ClaimRevoked {
+                    who: "Alice".to_string(),
+                    claim: "hello".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn force_revoke_claim() {
+        let mut pallet = super::Pallet::<TestConfig>::new(&Storage::default());
+        assert_eq!(
+            pallet.create_claim(signed("Alice"), "hello".to_string()),
+            Ok(())
+        );
+        assert_eq!(
+            pallet.force_revoke_claim(signed("Alice"), "hello".to_string()),
+            Err(DispatchError::BadOrigin)
+        );
+        assert_eq!(
+            pallet.create_claim(RawOrigin::None, "world".to_string()),
+            Err(DispatchError::BadOrigin)
+        );
+
+        assert_eq!(
+            pallet.force_revoke_claim(RawOrigin::Root, "hello".to_string()),
+            Ok(())
+        );
+        assert_eq!(pallet.get_claim(&"hello".to_string()), None);
         assert_eq!(
-            pallet.create_claim(&"Bob", "hello"),
-            Err("this content is already claimed")
+            pallet.force_revoke_claim(RawOrigin::Root, "hello".to_string()),
+            Err(Error::NoSuchClaim.into())
         );
         assert_eq!(
-            pallet.revoke_claim(&"Bob", "hello"),
-            Err("caller is not the owner of the claim")
+            pallet.take_events().last(),
+            Some(&Event::ClaimRevoked {
+                who: "Alice".to_string(),
+                claim: "hello".to_string()
+            })
         );
-        assert_eq!(pallet.revoke_claim(&"Alice", "hello"), Ok(()));
-        assert_eq!(pallet.get_claim(&"hello"), None);
     }
 }
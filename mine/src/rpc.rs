@@ -0,0 +1,674 @@
+use crate::chain::BlockId;
+use crate::node::Node;
+use crate::pool;
+use crate::support::{
+    codec::{Decode, Encode},
+    from_hex, to_hex,
+};
+use crate::ws::{Sink, Topic};
+use crate::{types, Runtime};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The port the RPC server listens on by default.
+pub const DEFAULT_RPC_PORT: u16 = 9933;
+/// The number of connections the RPC server serves at once by default.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 100;
+/// The largest body of an HTTP request the server reads, in bytes.
+const MAX_REQUEST_SIZE: usize = 1 << 20;
+/// The largest request line and headers of an HTTP request the server reads, in bytes.
+const MAX_HEAD_SIZE: usize = 8 << 10;
+/// How long the server waits on a client which does not send or receive, before closing the
+/// connection.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How the HTTP server accepts connections.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The port to listen on, on localhost, where port 0 picks any free port.
+    pub port: u16,
+    /// The maximum number of connections served at once. Further connections are answered with
+    /// `503 Service Unavailable` until one closes.
+    pub max_connections: usize,
+    /// The origins browsers may call the server from, or `*` for any origin. Requests which come
+    /// from another origin are refused. Clients which are not browsers send no origin, and are
+    /// always served.
+    pub cors: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            port: DEFAULT_RPC_PORT,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            cors: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    fn allows(&self, origin: &str) -> bool {
+        self.cors
+            .iter()
+            .any(|allowed| allowed == "*" || allowed == origin)
+    }
+}
+
+/// The error of a failed call, as defined by JSON-RPC 2.0.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Error {
+    pub code: i64,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (code {})", self.message, self.code)
+    }
+}
+
+impl Error {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Error {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn parse_error() -> Self {
+        Error::new(-32700, "parse error")
+    }
+
+    fn invalid_request() -> Self {
+        Error::new(-32600, "invalid request")
+    }
+
+    fn method_not_found(method: &str) -> Self {
+        Error::new(-32601, format!("method not found: {}", method))
+    }
+
+    fn invalid_params(reason: impl Into<String>) -> Self {
+        Error::new(-32602, reason)
+    }
+
+    fn internal(reason: impl Into<String>) -> Self {
+        Error::new(-32603, reason)
+    }
+
+    /// The pool did not accept a transaction.
+    fn invalid_transaction(e: pool::Error) -> Self {
+        Error::new(1010, format!("invalid transaction: {}", e))
+    }
+}
+
+/// A call to a method, waiting for the node to answer it.
+pub struct Request {
+    method: String,
+    params: Vec<Value>,
+    reply: Sender<Result<Value, Error>>,
+    /// Where notifications go, if the call came through a connection which can receive them.
+    sink: Option<Sink>,
+}
+
+/// Listen for JSON-RPC calls over HTTP on localhost, as configured by `config`.
+///
+/// Every connection is served by its own thread, but the node is only used from the thread which
+/// owns it. The calls are passed to that thread through `requests`, and it answers them with
+/// `serve_until`. Returns the address the server listens on.
+pub fn start(config: Config, requests: Sender<Request>) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, config.port))?;
+    let addr = listener.local_addr()?;
+    let config = Arc::new(config);
+    // only this thread adds connections, so the limit cannot be overrun
+    let connections = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            if connections.load(Ordering::SeqCst) >= config.max_connections {
+                let _ = write_response(&mut stream, "503 Service Unavailable", "", None);
+                continue;
+            }
+            connections.fetch_add(1, Ordering::SeqCst);
+            let (config, connections) = (config.clone(), connections.clone());
+            let requests = requests.clone();
+            thread::spawn(move || {
+                let _ = serve_connection(stream, &config, &requests);
+                connections.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+    Ok(addr)
+}
+
+/// Answer the calls passed by the server until `deadline`.
+pub fn serve_until(node: &mut Node, requests: &Receiver<Request>, deadline: Instant) {
+    while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+        match requests.recv_timeout(timeout) {
+            Ok(request) => serve(node, request),
+            Err(RecvTimeoutError::Timeout) => return,
+            Err(RecvTimeoutError::Disconnected) => return thread::sleep(timeout),
+        }
+    }
+}
+
+/// Answer a single call passed by a server.
+///
+/// Calls to subscribe and unsubscribe are only answered for connections which can receive
+/// notifications. Every other call is answered by `handle`.
+pub fn serve(node: &mut Node, request: Request) {
+    let Request {
+        method,
+        params,
+        reply,
+        sink,
+    } = request;
+    let topic = match method.as_str() {
+        "chain_subscribeNewHeads" | "chain_unsubscribeNewHeads" => Some(Topic::NewHeads),
+        "state_subscribeEvents" | "state_unsubscribeEvents" => Some(Topic::Events),
+        _ => None,
+    };
+    let result = match (topic, sink) {
+        (None, _) => handle(node, &method, &params),
+        (Some(_), None) => Err(Error::new(
+            -32000,
+            "subscriptions need a WebSocket connection",
+        )),
+        (Some(topic), Some(sink)) if method.contains("_subscribe") => {
+            Ok(json!(node.subscriptions().subscribe(topic, sink)))
+        }
+        (Some(topic), Some(sink)) => param::<u64>(&params, 0)
+            .map(|id| json!(node.subscriptions().unsubscribe(topic, id, &sink))),
+    };
+    let _ = reply.send(result);
+}
+
+/// Answer a call to `method` with `params`. State is read at the best block.
+pub fn handle(node: &mut Node, method: &str, params: &[Value]) -> Result<Value, Error> {
+    match method {
+        "author_submitExtrinsic" => {
+            let bytes = param::<String>(params, 0)?;
+            let extrinsic = from_hex(&bytes)
+                .and_then(|bytes| types::Extrinsic::decode_all(&bytes).ok())
+                .ok_or_else(|| Error::invalid_params("invalid extrinsic encoding"))?;
+            let hash = node
+                .submit_extrinsic(extrinsic)
+                .map_err(Error::invalid_transaction)?;
+            Ok(json!(hash))
+        }
+        "chain_getBlock" => {
+            let id = block_id(node, params)?;
+            Ok(node.chain().block(id).map_or(Value::Null, |block| {
+                let extrinsics = block
+                    .extrinsics
+                    .iter()
+                    .map(|extrinsic| to_hex(&extrinsic.encode()))
+                    .collect::<Vec<_>>();
+                json!({ "header": block.header, "extrinsics": extrinsics })
+            }))
+        }
+        "chain_getBlockHash" => {
+            let number = param::<Option<types::BlockNumber>>(params, 0)?;
+            let chain = node.chain();
+            Ok(json!(chain.hash(number.unwrap_or(chain.best_number()))))
+        }
+        "chain_getGenesisHash" => {
+            // The hash which extrinsics are signed for, whatever the number of the genesis block.
+            Ok(json!(node.chain().genesis_hash()))
+        }
+        "chain_getHeader" => {
+            let id = block_id(node, params)?;
+            Ok(json!(node.chain().header(id)))
+        }
+        "state_getBalance" => {
+            let who = param::<types::AccountID>(params, 0)?;
+            // Balances may not fit in the numbers of JavaScript, so they are written as strings.
+            let balance = node.runtime().balances.get_balance(&who);
+            Ok(json!(balance.to_string()))
+        }
+        "state_getClaim" => {
+            let claim = param::<String>(params, 0)?;
+            Ok(json!(node.runtime().proof_of_existance.get_claim(&claim)))
+        }
+        "system_accountNonce" => {
+            // The nonce of the next transaction of the account, counting those in the pool.
+            let who = param::<types::AccountID>(params, 0)?;
+            Ok(json!(node.next_nonce(&who)))
+        }
+        "state_getMetadata" => Ok(json!(Runtime::metadata())),
+        _ => Err(Error::method_not_found(method)),
+    }
+}
+
+/// The parameter at `index`. A missing parameter is read as `null`.
+fn param<T: DeserializeOwned>(params: &[Value], index: usize) -> Result<T, Error> {
+    let value = params.get(index).cloned().unwrap_or(Value::Null);
+    serde_json::from_value(value)
+        .map_err(|e| Error::invalid_params(format!("parameter {}: {}", index, e)))
+}
+
+/// The block given as first parameter, or the best block if there is none.
+fn block_id(node: &Node, params: &[Value]) -> Result<BlockId, Error> {
+    let id = param::<Option<BlockId>>(params, 0)?;
+    Ok(id.unwrap_or(BlockId::Hash(node.chain().best_hash())))
+}
+
+/// Read a single HTTP request from `stream`, and answer the calls in its body.
+fn serve_connection(
+    stream: TcpStream,
+    config: &Config,
+    requests: &Sender<Request>,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut stream = stream;
+    let too_large = "431 Request Header Fields Too Large";
+
+    let mut head_left = MAX_HEAD_SIZE;
+    let Some(request_line) = read_head_line(&mut reader, &mut head_left)? else {
+        return write_response(&mut stream, too_large, "", None);
+    };
+    let mut content_length = 0;
+    let mut origin = None;
+    loop {
+        let Some(line) = read_head_line(&mut reader, &mut head_left)? else {
+            return write_response(&mut stream, too_large, "", None);
+        };
+        if line.is_empty() {
+            return Ok(());
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(usize::MAX);
+            } else if name.eq_ignore_ascii_case("origin") {
+                origin = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    let origin = origin.as_deref();
+    if origin.is_some_and(|origin| !config.allows(origin)) {
+        return write_response(&mut stream, "403 Forbidden", "", None);
+    }
+    match request_line.split_whitespace().next() {
+        // Browsers check that the server accepts calls from other origins first.
+        Some("OPTIONS") => return write_response(&mut stream, "204 No Content", "", origin),
+        Some("POST") => {}
+        _ => return write_response(&mut stream, "405 Method Not Allowed", "", origin),
+    }
+    if content_length > MAX_REQUEST_SIZE {
+        return write_response(&mut stream, "413 Payload Too Large", "", origin);
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    match answer_all(&body, requests, None) {
+        Some(response) => write_response(&mut stream, "200 OK", &response.to_string(), origin),
+        None => write_response(&mut stream, "204 No Content", "", origin),
+    }
+}
+
+/// Read a line of the request line and headers of a request, of which `head_left` bytes may still
+/// be read. Returns nothing if the line does not fit, or an empty line if the client closed the
+/// connection.
+fn read_head_line(reader: &mut impl BufRead, head_left: &mut usize) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    let read = reader
+        .by_ref()
+        .take(*head_left as u64)
+        .read_line(&mut line)?;
+    *head_left -= read;
+    if *head_left == 0 && !line.ends_with('\n') {
+        return Ok(None);
+    }
+    Ok(Some(line))
+}
+
+/// Answer the call, or the batch of calls, in `body`. Returns nothing if there is nothing to answer,
+/// when all the calls are notifications.
+pub fn answer_all(body: &[u8], requests: &Sender<Request>, sink: Option<&Sink>) -> Option<Value> {
+    match serde_json::from_slice::<Value>(body) {
+        Err(_) => Some(response(Value::Null, Err(Error::parse_error()))),
+        Ok(Value::Array(calls)) if !calls.is_empty() => {
+            let responses = calls
+                .into_iter()
+                .filter_map(|call| answer(call, requests, sink))
+                .collect::<Vec<_>>();
+            (!responses.is_empty()).then_some(Value::Array(responses))
+        }
+        Ok(call) => answer(call, requests, sink),
+    }
+}
+
+/// Pass a call to the node and wait for its answer. Returns the response to the call, or nothing if
+/// the call is a notification, which has no id.
+fn answer(call: Value, requests: &Sender<Request>, sink: Option<&Sink>) -> Option<Value> {
+    let id = call.get("id").cloned();
+    let method = call.get("method").and_then(Value::as_str);
+    let params = match call.get("params") {
+        None => Some(Vec::new()),
+        Some(Value::Array(params)) => Some(params.clone()),
+        Some(_) => None,
+    };
+    let (Some(method), Some(params), Some("2.0")) =
+        (method, params, call.get("jsonrpc").and_then(Value::as_str))
+    else {
+        return Some(response(
+            id.unwrap_or(Value::Null),
+            Err(Error::invalid_request()),
+        ));
+    };
+
+    let (reply, result) = mpsc::channel();
+    let request = Request {
+        method: method.to_string(),
+        params,
+        reply,
+        sink: sink.cloned(),
+    };
+    let result = match requests.send(request) {
+        Ok(()) => result
+            .recv()
+            .unwrap_or_else(|_| Err(Error::internal("the node stopped"))),
+        Err(_) => Err(Error::internal("the node stopped")),
+    };
+    id.map(|id| response(id, result))
+}
+
+/// The response to the call with `id`.
+fn response(id: Value, result: Result<Value, Error>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    }
+}
+
+/// Call `method` with `params` on the node whose RPC server listens on `addr`. The outer error
+/// means the node could not be reached, or did not answer with JSON-RPC.
+pub fn call(addr: SocketAddr, method: &str, params: Value) -> io::Result<Result<Value, Error>> {
+    let body = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }).to_string();
+    let mut stream = TcpStream::connect(addr)?;
+    write!(
+        stream,
+        "POST / HTTP/1.1\r\n\
+         Host: {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}",
+        addr,
+        body.len(),
+        body
+    )?;
+    // The server closes the connection once it answered.
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid response from the node");
+    let start = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(invalid)?;
+    let mut response =
+        serde_json::from_slice::<Value>(&response[start + 4..]).map_err(|_| invalid())?;
+    match response.get_mut("error") {
+        Some(error) => serde_json::from_value(error.take())
+            .map(Err)
+            .map_err(|_| invalid()),
+        None => Ok(Ok(response["result"].take())),
+    }
+}
+
+/// Write a response, which allows `origin` to read it if the request came from a browser.
+fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    body: &str,
+    origin: Option<&str>,
+) -> io::Result<()> {
+    let cors = match origin {
+        Some(origin) => format!(
+            "Access-Control-Allow-Origin: {}\r\n\
+             Access-Control-Allow-Methods: POST, OPTIONS\r\n\
+             Access-Control-Allow-Headers: Content-Type\r\n\
+             Vary: Origin\r\n",
+            origin
+        ),
+        None => String::new(),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         {}\
+         Connection: close\r\n\
+         \r\n\
+         {}",
+        status,
+        body.len(),
+        cors,
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node;
+    use crate::support::db::test_dir;
+    use crate::tests::{account, test_genesis, transfer};
+    use std::{fs, time::Duration};
+
+    #[test]
+    fn methods_read_the_best_block() {
+        let dir = test_dir("rpc-methods");
+        let mut node = Node::open(&dir, &test_genesis(), node::Config::default()).unwrap();
+        let alice = serde_json::to_value(account("Alice")).unwrap();
+        let bob = serde_json::to_value(account("Bob")).unwrap();
+
+        let extrinsic = to_hex(&transfer(node.runtime(), "Bob", 30).encode());
+        let hash = handle(&mut node, "author_submitExtrinsic", &[json!(extrinsic)]).unwrap();
+        assert_eq!(
+            handle(&mut node, "author_submitExtrinsic", &[json!(extrinsic)])
+                .unwrap_err()
+                .code,
+            1010
+        );
+        assert_eq!(
+            handle(
+                &mut node,
+                "system_accountNonce",
+                std::slice::from_ref(&alice)
+            ),
+            Ok(json!(1))
+        );
+        let block = node.author_block().unwrap();
+
+        let by_number = handle(&mut node, "chain_getBlock", &[json!(1)]).unwrap();
+        assert_eq!(by_number["extrinsics"], json!([extrinsic]));
+        assert_eq!(by_number["header"]["block_number"], json!(1));
+        let best = handle(&mut node, "chain_getBlock", &[]).unwrap();
+        assert_eq!(best, by_number);
+        let header = handle(&mut node, "chain_getHeader", &[json!(block.hash())]).unwrap();
+        assert_eq!(header, json!(block.header));
+        assert_eq!(
+            header["parent_hash"],
+            json!(node.runtime().system.genesis_hash())
+        );
+        assert_eq!(
+            handle(&mut node, "chain_getHeader", &[json!(2)]),
+            Ok(Value::Null)
+        );
+        assert_eq!(
+            handle(&mut node, "chain_getBlockHash", &[json!(0)]),
+            Ok(json!(node.runtime().system.genesis_hash()))
+        );
+        assert_eq!(
+            handle(&mut node, "chain_getGenesisHash", &[]),
+            Ok(json!(node.runtime().system.genesis_hash()))
+        );
+        assert_eq!(
+            handle(&mut node, "chain_getBlockHash", &[]),
+            Ok(json!(block.hash()))
+        );
+        assert_eq!(hash, json!(crate::support::hash_of(&block.extrinsics[0])));
+
+        assert_eq!(
+            handle(&mut node, "state_getBalance", &[bob]),
+            Ok(json!("30"))
+        );
+        assert_eq!(
+            handle(&mut node, "state_getClaim", &[json!("Hello")]),
+            Ok(Value::Null)
+        );
+        assert_eq!(
+            handle(&mut node, "system_accountNonce", &[alice]),
+            Ok(json!(1))
+        );
+        let metadata = handle(&mut node, "state_getMetadata", &[]).unwrap();
+        assert_eq!(metadata["pallets"][0]["name"], json!("balances"));
+
+        assert_eq!(
+            handle(&mut node, "state_getBalance", &[json!("Bob")])
+                .unwrap_err()
+                .code,
+            -32602
+        );
+        assert_eq!(
+            handle(&mut node, "state_getBalances", &[])
+                .unwrap_err()
+                .code,
+            -32601
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn calls_are_answered_over_http() {
+        let dir = test_dir("rpc-http");
+        let mut node = Node::open(&dir, &test_genesis(), node::Config::default()).unwrap();
+        let (sender, requests) = mpsc::channel();
+        let config = Config {
+            port: 0,
+            ..Config::default()
+        };
+        let addr = start(config, sender).unwrap();
+
+        let post = move |body: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(
+                stream,
+                "POST / HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let client = thread::spawn(move || {
+            let nonce = post(&format!(
+                r#"{{"jsonrpc":"2.0","id":1,"method":"system_accountNonce","params":["{:?}"]}}"#,
+                account("Alice")
+            ));
+            let batch = post(
+                r#"[{"jsonrpc":"2.0","id":"a","method":"chain_getHeader","params":[0]},
+                    {"jsonrpc":"2.0","method":"chain_getHeader"},
+                    {"jsonrpc":"2.0","id":"b","method":"nope"}]"#,
+            );
+            let invalid = post("{");
+            (nonce, batch, invalid)
+        });
+        // The client makes four calls, one of which is a notification.
+        for _ in 0..4 {
+            let request = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+            serve(&mut node, request);
+        }
+        let (nonce, batch, invalid) = client.join().unwrap();
+
+        let body = |response: &str| {
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+            let (_, body) = response.split_once("\r\n\r\n").unwrap();
+            serde_json::from_str::<Value>(body).unwrap()
+        };
+        assert_eq!(
+            body(&nonce),
+            json!({ "jsonrpc": "2.0", "id": 1, "result": 0 })
+        );
+        let batch = body(&batch);
+        assert_eq!(batch.as_array().unwrap().len(), 2);
+        assert_eq!(batch[0]["id"], json!("a"));
+        assert_eq!(batch[0]["result"]["block_number"], json!(0));
+        assert_eq!(batch[1]["error"]["code"], json!(-32601));
+        assert_eq!(body(&invalid)["error"]["code"], json!(-32700));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn the_server_limits_what_it_serves() {
+        let (sender, _requests) = mpsc::channel();
+        let config = Config {
+            port: 0,
+            cors: vec!["http://localhost:3000".to_string()],
+            ..Config::default()
+        };
+        let addr = start(config.clone(), sender.clone()).unwrap();
+        let send = |request: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let preflight = send("OPTIONS / HTTP/1.1\r\nOrigin: http://localhost:3000\r\n\r\n");
+        assert!(preflight.starts_with("HTTP/1.1 204 No Content\r\n"));
+        assert!(preflight.contains("Access-Control-Allow-Origin: http://localhost:3000\r\n"));
+        let other = send("OPTIONS / HTTP/1.1\r\nOrigin: http://example.com\r\n\r\n");
+        assert!(other.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        let no_origin = send("OPTIONS / HTTP/1.1\r\n\r\n");
+        assert!(no_origin.starts_with("HTTP/1.1 204 No Content\r\n"));
+        assert!(!no_origin.contains("Access-Control-Allow-Origin"));
+
+        // a header which never ends, sent in full so the server reads all of it
+        let request_line = "POST / HTTP/1.1\r\n";
+        let padding = "a".repeat(MAX_HEAD_SIZE - request_line.len());
+        let large = send(&format!("{}{}", request_line, padding));
+        assert!(large.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+
+        let config = Config {
+            max_connections: 1,
+            ..config
+        };
+        let addr = start(config, sender).unwrap();
+        // the first connection is served until it sends its request, or times out
+        let mut idle = TcpStream::connect(addr).unwrap();
+        let mut busy = String::new();
+        let mut other = TcpStream::connect(addr).unwrap();
+        other.read_to_string(&mut busy).unwrap();
+        assert!(busy.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        idle.write_all(b"OPTIONS / HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        idle.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 204 No Content\r\n"));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::support::{
+    storage::{Storage, StorageValue},
+    BuildGenesis, DispatchNested, DispatchResult, GetWeight,
+};
+use crate::system::{ensure_signed, RawOrigin};
+use serde::{Deserialize, Serialize};
+
+/// The weight of a sudo call itself, on top of the weight of the call it dispatches.
+const SUDO_WEIGHT: crate::support::Weight = 10;
+
+pub trait Config: crate::system::Config<RuntimeCall: GetWeight> {}
+
+/// Errors which can be returned by the Sudo Module.
+#[macros::error]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The caller is not the sudo key.
+    RequireSudo,
+}
+
+/// Events deposited by the Sudo Module.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(bound(serialize = "T::AccountId: Serialize"))]
+pub enum Event<T: Config> {
+    /// A call was dispatched on behalf of the sudo key, with this result.
+    Sudid { sudo_result: DispatchResult },
+    /// The sudo key was changed.
+    KeyChanged {
+        old: Option<T::AccountId>,
+        new: T::AccountId,
+    },
+}
+
+/// The sudo key at genesis. Without a key, nobody can use this pallet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "T::AccountId: Serialize",
+    deserialize = "T::AccountId: Deserialize<'de>"
+))]
+pub struct GenesisConfig<T: Config> {
+    pub key: Option<T::AccountId>,
+}
+
+impl<T: Config> Default for GenesisConfig<T> {
+    fn default() -> Self {
+        GenesisConfig { key: None }
+    }
+}
+
+/// This is the Sudo Module.
+/// It lets a single account, the sudo key, dispatch any call as root, or on behalf of any account.
+#[derive(Debug, Clone)]
+pub struct Pallet<T: Config> {
+    /// The account allowed to use this pallet.
+    key: StorageValue<T::AccountId>,
+    /// Events deposited since the runtime last collected them.
+    events: Vec<Event<T>>,
+    /// Calls waiting for the runtime to dispatch them.
+    nested_calls: Vec<(T::RuntimeOrigin, T::RuntimeCall)>,
+}
+
+impl<T: Config> Pallet<T> {
+    /// Create a new instance of the Sudo Module, which keeps its state in `storage`.
+    pub fn new(storage: &Storage) -> Self {
+        Self {
+            key: StorageValue::new(storage, "Sudo", "Key"),
+            events: Vec::new(),
+            nested_calls: Vec::new(),
+        }
+    }
+
+    /// Deposit an event, to be collected by the runtime.
+    pub fn deposit_event(&mut self, event: Event<T>) {
+        self.events.push(event);
+    }
+
+    /// Take all the events deposited since the last call.
+    pub fn take_events(&mut self) -> Vec<Event<T>> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Get the sudo key, if any.
+    pub fn key(&self) -> Option<T::AccountId> {
+        self.key.get()
+    }
+
+    /// Ensure that `origin` is signed by the sudo key.
+    fn ensure_sudo(&self, origin: T::RuntimeOrigin) -> DispatchResult {
+        let caller = ensure_signed(origin)?;
+        if self.key.get() != Some(caller) {
+            return Err(Error::RequireSudo.into());
+        }
+        Ok(())
+    }
+}
+
+// The calls are boxed, since `RuntimeCall` contains the calls of this pallet.
+#[macros::call]
+#[allow(clippy::boxed_local)]
+impl<T: Config> Pallet<T> {
+    /// Dispatch `call` with the `Root` origin.
+    /// Only the sudo key can do this. The result of `call` is reported in a `Sudid` event, and
+    /// does not affect the result of this call.
+    #[weight(SUDO_WEIGHT.saturating_add(call.weight()))]
+    pub fn sudo(&mut self, origin: T::RuntimeOrigin, call: Box<T::RuntimeCall>) -> DispatchResult {
+        self.ensure_sudo(origin)?;
+        self.nested_calls.push((RawOrigin::Root.into(), *call));
+        Ok(())
+    }
+
+    /// Dispatch `call` with a `Signed` origin for the account `who`.
+    /// Only the sudo key can do this. The result of `call` is reported in a `Sudid` event, and
+    /// does not affect the result of this call.
+    #[weight(SUDO_WEIGHT.saturating_add(call.weight()))]
+    pub fn sudo_as(
+        &mut self,
+        origin: T::RuntimeOrigin,
+        who: T::AccountId,
+        call: Box<T::RuntimeCall>,
+    ) -> DispatchResult {
+        self.ensure_sudo(origin)?;
+        self.nested_calls
+            .push((RawOrigin::Signed(who).into(), *call));
+        Ok(())
+    }
+
+    /// Hand the sudo key over to the account `new`.
+    /// Only the sudo key can do this.
+    #[weight(SUDO_WEIGHT)]
+    pub fn set_key(&mut self, origin: T::RuntimeOrigin, new: T::AccountId) -> DispatchResult {
+        self.ensure_sudo(origin)?;
+        let old = self.key.mutate(|key| key.replace(new.clone()));
+        self.deposit_event(Event::KeyChanged { old, new });
+        Ok(())
+    }
+}
+
+impl<T: Config> BuildGenesis for Pallet<T> {
+    type GenesisConfig = GenesisConfig<T>;
+
+    fn build_genesis(storage: &Storage, config: &GenesisConfig<T>) -> Self {
+        let pallet = Self::new(storage);
+        if let Some(key) = &config.key {
+            pallet.key.put(key);
+        }
+        pallet
+    }
+}
+
+// The calls sudo'd by the key are handed to the runtime, which reports their result back.
+impl<T: Config> DispatchNested<T::RuntimeOrigin, T::RuntimeCall> for Pallet<T> {
+    fn take_nested_calls(&mut self) -> Vec<(T::RuntimeOrigin, T::RuntimeCall)> {
+        std::mem::take(&mut self.nested_calls)
+    }
+
+    fn on_nested_call_result(&mut self, sudo_result: DispatchResult) {
+        self.deposit_event(Event::Sudid { sudo_result });
+    }
+}
+
+// This pallet does not accept any unsigned calls.
+impl<T: Config> crate::support::ValidateUnsigned for Pallet<T> {
+    type Call = Call<T>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::support::{DispatchError, Weight, H256};
+
+    #[derive(Debug, PartialEq)]
+    struct TestConfig;
+
+    /// A call which does nothing, standing in for the calls of a runtime.
+    #[derive(Debug, Clone, PartialEq)]
+    struct TestCall;
+
+    impl GetWeight for TestCall {
+        fn weight(&self) -> Weight {
+            5
+        }
+    }
+
+    impl crate::system::Config for TestConfig {
+        type AccountId = String;
+        type BlockNumber = u32;
+        type Nonce = u32;
+        type RuntimeEvent = ();
+        type RuntimeCall = TestCall;
+        type RuntimeOrigin = RawOrigin<String>;
+        const MAX_BLOCK_WEIGHT: u64 = 1_000;
+    }
+    impl Config for TestConfig {}
+
+    fn signed(who: &str) -> RawOrigin<String> {
+        RawOrigin::Signed(who.to_string())
+    }
+
+    fn pallet_with_key(storage: &Storage, key: &str) -> Pallet<TestConfig> {
+        Pallet::build_genesis(
+            storage,
+            &GenesisConfig {
+                key: Some(key.to_string()),
+            },
+        )
+    }
+
+    #[test]
+    fn sudo() {
+        let mut pallet = pallet_with_key(&Storage::default(), "Alice");
+        assert_eq!(
+            pallet.sudo(signed("Bob"), Box::new(TestCall)),
+            Err(Error::RequireSudo.into())
+        );
+        assert_eq!(
+            pallet.sudo(RawOrigin::Root, Box::new(TestCall)),
+            Err(DispatchError::BadOrigin)
+        );
+        assert_eq!(pallet.take_nested_calls(), vec![]);
+
+        assert_eq!(pallet.sudo(signed("Alice"), Box::new(TestCall)), Ok(()));
+        assert_eq!(
+            pallet.sudo_as(signed("Alice"), "Bob".to_string(), Box::new(TestCall)),
+            Ok(())
+        );
+        assert_eq!(
+            pallet.take_nested_calls(),
+            vec![(RawOrigin::Root, TestCall), (signed("Bob"), TestCall)]
+        );
+        assert_eq!(
+            Call::<TestConfig>::sudo {
+                call: Box::new(TestCall)
+            }
+            .weight(),
+            SUDO_WEIGHT + 5
+        );
+
+        pallet.on_nested_call_result(Err(DispatchError::BadOrigin));
+        assert_eq!(
+            pallet.take_events(),
+            vec![Event::Sudid {
+                sudo_result: Err(DispatchError::BadOrigin)
+            }]
+        );
+    }
+
+    #[test]
+    fn set_key() {
+        let storage = Storage::default();
+        let mut pallet = Pallet::<TestConfig>::new(&storage);
+        assert_eq!(storage.root(), H256::default());
+        assert_eq!(
+            pallet.set_key(signed("Alice"), "Alice".to_string()),
+            Err(Error::RequireSudo.into())
+        );
+
+        let storage = Storage::default();
+        let mut pallet = pallet_with_key(&storage, "Alice");
+        let root = storage.root();
+        assert_eq!(pallet.set_key(signed("Alice"), "Bob".to_string()), Ok(()));
+        assert_eq!(pallet.key(), Some("Bob".to_string()));
+        assert_ne!(storage.root(), root);
+        assert_eq!(
+            pallet.set_key(signed("Alice"), "Alice".to_string()),
+            Err(Error::RequireSudo.into())
+        );
+        assert_eq!(
+            pallet.take_events(),
+            vec![Event::KeyChanged {
+                old: Some("Alice".to_string()),
+                new: "Bob".to_string()
+            }]
+        );
+    }
+}
@@ -0,0 +1,456 @@
+use super::{
+    crypto::{Public, Signature},
+    Block, Extrinsic, ExtrinsicSignature, Header, H256,
+};
+
+/// A compact, deterministic binary encoding, modeled after the SCALE codec.
+///
+/// - Integers are encoded as fixed width little endian.
+/// - Collections and strings are prefixed with their length as a `Compact` integer.
+/// - Enums are encoded as the `u8` index of their variant, followed by its fields.
+/// - Structs and tuples are the concatenation of their fields, in order.
+///
+/// Every value has exactly one encoding, so the encoding is what we hash and sign.
+pub trait Encode {
+    /// Append the encoding of `self` to `dest`.
+    fn encode_to(&self, dest: &mut Vec<u8>);
+
+    /// The encoding of `self`.
+    fn encode(&self) -> Vec<u8> {
+        let mut dest = Vec::new();
+        self.encode_to(&mut dest);
+        dest
+    }
+}
+
+/// The inverse of `Encode`.
+pub trait Decode: Sized {
+    /// Decode a value from the start of `input`, and advance `input` past it.
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError>;
+
+    /// Decode a value from exactly all of `bytes`.
+    fn decode_all(mut bytes: &[u8]) -> Result<Self, DecodeError> {
+        let value = Self::decode(&mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(DecodeError("input has bytes left over"));
+        }
+        Ok(value)
+    }
+}
+
+/// The reason some bytes could not be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError(pub &'static str);
+
+/// Take the next `len` bytes of `input`.
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], DecodeError> {
+    if input.len() < len {
+        return Err(DecodeError("not enough bytes"));
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Ok(bytes)
+}
+
+macro_rules! impl_int {
+    ($($t:ty),*) => {$(
+        impl Encode for $t {
+            fn encode_to(&self, dest: &mut Vec<u8>) {
+                dest.extend_from_slice(&self.to_le_bytes());
+            }
+        }
+
+        impl Decode for $t {
+            fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+                let bytes = take(input, core::mem::size_of::<$t>())?;
+                Ok(<$t>::from_le_bytes(bytes.try_into().expect("length was checked")))
+            }
+        }
+    )*};
+}
+
+impl_int!(u8, u16, u32, u64, u128);
+
+/// An integer encoded in as few bytes as possible, as used for lengths. The two lowest bits of
+/// the first byte tell the mode:
+///
+/// - `0b00`: a single byte, for values below `2^6`.
+/// - `0b01`: two bytes, for values below `2^14`.
+/// - `0b10`: four bytes, for values below `2^30`.
+/// - `0b11`: the upper six bits are the number of following bytes minus four, which hold the
+///   value as little endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compact(pub u64);
+
+impl Encode for Compact {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        let value = self.0;
+        match value {
+            0..=0x3f => dest.push((value as u8) << 2),
+            0x40..=0x3fff => ((value as u16) << 2 | 0b01).encode_to(dest),
+            0x4000..=0x3fff_ffff => ((value as u32) << 2 | 0b10).encode_to(dest),
+            _ => {
+                let bytes = value.to_le_bytes();
+                let len = 8 - value.leading_zeros() as usize / 8;
+                dest.push(((len - 4) as u8) << 2 | 0b11);
+                dest.extend_from_slice(&bytes[..len]);
+            }
+        }
+    }
+}
+
+impl Decode for Compact {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let first = u8::decode(input)?;
+        let (value, min) = match first & 0b11 {
+            0b00 => return Ok(Compact((first >> 2) as u64)),
+            0b01 => {
+                let value = u16::from_le_bytes([first, u8::decode(input)?]) >> 2;
+                (value as u64, 0x40)
+            }
+            0b10 => {
+                let rest = take(input, 3)?;
+                let value = u32::from_le_bytes([first, rest[0], rest[1], rest[2]]) >> 2;
+                (value as u64, 0x4000)
+            }
+            _ => {
+                let len = (first >> 2) as usize + 4;
+                if len > 8 {
+                    return Err(DecodeError("compact integer is too large"));
+                }
+                let mut bytes = [0u8; 8];
+                bytes[..len].copy_from_slice(take(input, len)?);
+                (u64::from_le_bytes(bytes), 0x4000_0000)
+            }
+        };
+        // Only the shortest encoding of a value is valid.
+        if value < min || (first & 0b11 == 0b11 && value >> (((first >> 2) as u64 + 3) * 8) == 0) {
+            return Err(DecodeError("compact integer is not in its shortest form"));
+        }
+        Ok(Compact(value))
+    }
+}
+
+/// Decode a `Compact` length prefix.
+fn decode_len(input: &mut &[u8]) -> Result<usize, DecodeError> {
+    let len = Compact::decode(input)?.0;
+    usize::try_from(len).map_err(|_| DecodeError("length does not fit in memory"))
+}
+
+impl Encode for bool {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        dest.push(*self as u8);
+    }
+}
+
+impl Decode for bool {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DecodeError("invalid bool")),
+        }
+    }
+}
+
+impl Encode for () {
+    fn encode_to(&self, _dest: &mut Vec<u8>) {}
+}
+
+impl Decode for () {
+    fn decode(_input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(())
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        (**self).encode_to(dest)
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for Box<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        (**self).encode_to(dest)
+    }
+}
+
+impl<T: Decode> Decode for Box<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        T::decode(input).map(Box::new)
+    }
+}
+
+impl Encode for str {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        Compact(self.len() as u64).encode_to(dest);
+        dest.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Encode for String {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.as_str().encode_to(dest)
+    }
+}
+
+impl Decode for String {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = decode_len(input)?;
+        let bytes = take(input, len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError("invalid utf8"))
+    }
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        Compact(self.len() as u64).encode_to(dest);
+        for item in self {
+            item.encode_to(dest);
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.as_slice().encode_to(dest)
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = decode_len(input)?;
+        // Every item takes at least a byte, except for zero sized ones, so a length larger than
+        // the input cannot be valid. This keeps a bad length from allocating too much.
+        let mut items = Vec::with_capacity(len.min(input.len()));
+        for _ in 0..len {
+            items.push(T::decode(input)?);
+        }
+        Ok(items)
+    }
+}
+
+// Fixed size arrays have no length prefix.
+impl<T: Encode, const N: usize> Encode for [T; N] {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        for item in self {
+            item.encode_to(dest);
+        }
+    }
+}
+
+impl<T: Decode, const N: usize> Decode for [T; N] {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let items = (0..N)
+            .map(|_| T::decode(input))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(items
+            .try_into()
+            .unwrap_or_else(|_| unreachable!("exactly N items were decoded")))
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        match self {
+            None => dest.push(0),
+            Some(value) => {
+                dest.push(1);
+                value.encode_to(dest);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(input)?)),
+            _ => Err(DecodeError("invalid option")),
+        }
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: Encode),+> Encode for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode_to(&self, dest: &mut Vec<u8>) {
+                let ($($name,)+) = self;
+                $( $name.encode_to(dest); )+
+            }
+        }
+
+        impl<$($name: Decode),+> Decode for ($($name,)+) {
+            fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+                Ok(($($name::decode(input)?,)+))
+            }
+        }
+    };
+}
+
+impl_tuple!(A);
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
+
+impl Encode for H256 {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.0.encode_to(dest)
+    }
+}
+
+impl Decode for H256 {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Decode::decode(input).map(H256)
+    }
+}
+
+impl Encode for Public {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.0.encode_to(dest)
+    }
+}
+
+impl Decode for Public {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Decode::decode(input).map(Public)
+    }
+}
+
+impl Encode for Signature {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.0.encode_to(dest)
+    }
+}
+
+impl Decode for Signature {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Decode::decode(input).map(Signature)
+    }
+}
+
+impl<BlockNumber: Encode> Encode for Header<BlockNumber> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.parent_hash.encode_to(dest);
+        self.block_number.encode_to(dest);
+        self.state_root.encode_to(dest);
+        self.extrinsics_root.encode_to(dest);
+        self.author.encode_to(dest);
+    }
+}
+
+impl<BlockNumber: Decode> Decode for Header<BlockNumber> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Header {
+            parent_hash: Decode::decode(input)?,
+            block_number: Decode::decode(input)?,
+            state_root: Decode::decode(input)?,
+            extrinsics_root: Decode::decode(input)?,
+            author: Decode::decode(input)?,
+        })
+    }
+}
+
+impl<Nonce: Encode> Encode for ExtrinsicSignature<Nonce> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.signer.encode_to(dest);
+        self.nonce.encode_to(dest);
+        self.signature.encode_to(dest);
+    }
+}
+
+impl<Nonce: Decode> Decode for ExtrinsicSignature<Nonce> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(ExtrinsicSignature {
+            signer: Decode::decode(input)?,
+            nonce: Decode::decode(input)?,
+            signature: Decode::decode(input)?,
+        })
+    }
+}
+
+impl<Nonce: Encode, Call: Encode> Encode for Extrinsic<Nonce, Call> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.signature.encode_to(dest);
+        self.call.encode_to(dest);
+    }
+}
+
+impl<Nonce: Decode, Call: Decode> Decode for Extrinsic<Nonce, Call> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Extrinsic {
+            signature: Decode::decode(input)?,
+            call: Decode::decode(input)?,
+        })
+    }
+}
+
+impl<Header: Encode, Extrinsic: Encode> Encode for Block<Header, Extrinsic> {
+    fn encode_to(&self, dest: &mut Vec<u8>) {
+        self.header.encode_to(dest);
+        self.extrinsics.encode_to(dest);
+    }
+}
+
+impl<Header: Decode, Extrinsic: Decode> Decode for Block<Header, Extrinsic> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Block {
+            header: Decode::decode(input)?,
+            extrinsics: Decode::decode(input)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip<T: Encode + Decode + PartialEq + core::fmt::Debug>(value: T, encoded: &[u8]) {
+        assert_eq!(value.encode(), encoded);
+        assert_eq!(T::decode_all(encoded), Ok(value));
+    }
+
+    #[test]
+    fn primitives() {
+        roundtrip(42u8, &[42]);
+        roundtrip(0x0102u16, &[2, 1]);
+        roundtrip(1u32, &[1, 0, 0, 0]);
+        roundtrip(1u128, &[&[1][..], &[0; 15]].concat());
+        roundtrip(true, &[1]);
+        roundtrip(Some(7u8), &[1, 7]);
+        roundtrip(None::<u8>, &[0]);
+        roundtrip((1u8, 2u16), &[1, 2, 0]);
+        roundtrip("hi".to_string(), &[2 << 2, b'h', b'i']);
+        roundtrip(vec![1u8, 2, 3], &[3 << 2, 1, 2, 3]);
+        roundtrip([1u8, 2], &[1, 2]);
+
+        assert_eq!(bool::decode_all(&[2]), Err(DecodeError("invalid bool")));
+        assert_eq!(
+            u32::decode_all(&[1, 0]),
+            Err(DecodeError("not enough bytes"))
+        );
+        assert_eq!(
+            u8::decode_all(&[1, 0]),
+            Err(DecodeError("input has bytes left over"))
+        );
+    }
+
+    #[test]
+    fn compact() {
+        roundtrip(Compact(0), &[0]);
+        roundtrip(Compact(63), &[63 << 2]);
+        roundtrip(Compact(64), &[0x01, 0x01]);
+        roundtrip(Compact(0x3fff), &[0xfd, 0xff]);
+        roundtrip(Compact(0x4000), &[0x02, 0x00, 0x01, 0x00]);
+        roundtrip(Compact(0x3fff_ffff), &[0xfe, 0xff, 0xff, 0xff]);
+        roundtrip(Compact(0x4000_0000), &[0x03, 0x00, 0x00, 0x00, 0x40]);
+        roundtrip(Compact(u64::MAX), &[&[0x13][..], &[0xff; 8]].concat());
+
+        // Every value has a single valid encoding.
+        assert!(Compact::decode_all(&[0x01, 0x00]).is_err());
+        assert!(Compact::decode_all(&[0x07, 0x01, 0x00, 0x00, 0x00, 0x00]).is_err());
+        assert!(Compact::decode_all(&[0x17, 0, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+use super::{hash_bytes, parse_hex, write_hex};
+use ed25519_dalek::{Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// An ed25519 public key. Accounts are identified by the public key which signs for them.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Public(pub [u8; 32]);
+
+/// An ed25519 signature.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Signature(pub [u8; 64]);
+
+/// A key pair, which can sign messages on behalf of its `Public` key.
+pub struct Pair(SigningKey);
+
+impl Pair {
+    /// Create a key pair from a secret seed.
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Pair(SigningKey::from_bytes(&seed))
+    }
+
+    /// Create a well known development key pair from a name such as "Alice".
+    /// Anyone can derive these keys, so they must never hold anything of value.
+    pub fn from_name(name: &str) -> Self {
+        Self::from_seed(hash_bytes(format!("//{}", name).as_bytes()).0)
+    }
+
+    /// The public key of this pair.
+    pub fn public(&self) -> Public {
+        Public(self.0.verifying_key().to_bytes())
+    }
+
+    /// Sign `message` with the secret key of this pair.
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        Signature(self.0.sign(message).to_bytes())
+    }
+}
+
+impl Public {
+    /// Check that `signature` was produced by the secret key of this public key over `message`.
+    pub fn verify(&self, message: &[u8], signature: &Signature) -> bool {
+        let Ok(key) = VerifyingKey::from_bytes(&self.0) else {
+            return false;
+        };
+        let signature = ed25519_dalek::Signature::from_bytes(&signature.0);
+        key.verify(message, &signature).is_ok()
+    }
+}
+
+impl core::fmt::Debug for Public {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write_hex(f, &self.0)
+    }
+}
+
+impl core::str::FromStr for Public {
+    type Err = &'static str;
+
+    /// Parse a public key from its `0x` prefixed hex representation.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_hex(s).map(Public).ok_or("invalid public key")
+    }
+}
+
+// Public keys are written as hex strings in human readable formats such as a JSON genesis file.
+impl Serialize for Public {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:?}", self))
+    }
+}
+
+impl<'de> Deserialize<'de> for Public {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl core::fmt::Debug for Signature {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write_hex(f, &self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_and_verify() {
+        let alice = Pair::from_name("Alice");
+        let bob = Pair::from_name("Bob");
+        assert_eq!(alice.public(), Pair::from_name("Alice").public());
+        assert_ne!(alice.public(), bob.public());
+
+        let signature = alice.sign(b"hello");
+        assert!(alice.public().verify(b"hello", &signature));
+        assert!(!alice.public().verify(b"hello!", &signature));
+        assert!(!bob.public().verify(b"hello", &signature));
+    }
+
+    #[test]
+    fn public_from_hex() {
+        let alice = Pair::from_name("Alice").public();
+        assert_eq!(format!("{:?}", alice).parse::<Public>(), Ok(alice));
+        assert_eq!(
+            serde_json::from_str::<Public>(&serde_json::to_string(&alice).unwrap()).unwrap(),
+            alice
+        );
+        assert!("0x1234".parse::<Public>().is_err());
+        assert!(format!("{:?}", alice)[2..].parse::<Public>().is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use super::hash_bytes;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// The length of the header written before every record: the length of the record as a `u32`,
+/// followed by the first four bytes of its hash.
+const RECORD_HEADER_LEN: usize = 8;
+
+/// A file of records which only ever grows at its end, used to keep data on disk.
+///
+/// Every record is written with its length and a checksum, and synced before `append` returns. If
+/// the process dies in the middle of a write, the partial record at the end of the file is detected
+/// and dropped the next time the file is opened, so a record is either fully written or not at all.
+/// A damaged record followed by other records cannot come from an interrupted write, so it fails
+/// the opening of the file, which is left as it is.
+#[derive(Debug)]
+pub struct Log {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl Log {
+    /// Open the log at `path`, creating it if needed, and read all of its records.
+    pub fn open(path: &Path) -> io::Result<(Log, Vec<Vec<u8>>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let (records, size) = parse(&bytes)?;
+        // Anything left is a record whose write was interrupted.
+        if size < bytes.len() {
+            file.set_len(size as u64)?;
+            file.sync_all()?;
+        }
+
+        let log = Log {
+            path: path.to_path_buf(),
+            file,
+            size: size as u64,
+        };
+        Ok((log, records))
+    }
+
+    /// Read all the records of the log at `path`, without writing to it. A record whose write was
+    /// interrupted is skipped, but left in the file.
+    pub fn read(path: &Path) -> io::Result<Vec<Vec<u8>>> {
+        let (records, _) = parse(&fs::read(path)?)?;
+        Ok(records)
+    }
+
+    /// The size of the file in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Write `record` at the end of the log.
+    pub fn append(&mut self, record: &[u8]) -> io::Result<()> {
+        let bytes = frame(record);
+        self.file.write_all(&bytes)?;
+        self.file.sync_data()?;
+        self.size += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Replace all the records of the log with `records`.
+    ///
+    /// The new records are written to a temporary file first, which then takes the place of the
+    /// log, so the log holds either all of the old records or all of the new ones.
+    pub fn rewrite(&mut self, records: &[Vec<u8>]) -> io::Result<()> {
+        let tmp_path = self.path.with_extension("tmp");
+        let mut tmp = File::create(&tmp_path)?;
+        let bytes = records
+            .iter()
+            .flat_map(|record| frame(record))
+            .collect::<Vec<_>>();
+        tmp.write_all(&bytes)?;
+        tmp.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+        // The rename is only durable once the directory is synced, as the truncation in `open` is
+        // once the file is.
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.size = bytes.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Log {
+    /// Make every later write to the log fail, as on a full disk, or work again.
+    pub fn set_writable(&mut self, writable: bool) {
+        self.file = if writable {
+            OpenOptions::new().append(true).open(&self.path).unwrap()
+        } else {
+            File::open(&self.path).unwrap()
+        };
+    }
+}
+
+/// A record with its header.
+fn frame(record: &[u8]) -> Vec<u8> {
+    let len = u32::try_from(record.len()).expect("records are smaller than 4 GiB");
+    let mut bytes = Vec::with_capacity(RECORD_HEADER_LEN + record.len());
+    bytes.extend_from_slice(&len.to_le_bytes());
+    bytes.extend_from_slice(&hash_bytes(record).0[..4]);
+    bytes.extend_from_slice(record);
+    bytes
+}
+
+/// Read all the complete records in `bytes`. Also returns the size of those records with their
+/// headers, which is where the log ends if the write of its last record was interrupted.
+fn parse(bytes: &[u8]) -> io::Result<(Vec<Vec<u8>>, usize)> {
+    let mut records = Vec::new();
+    let mut input = bytes;
+    while let Some(record) = next_record(&mut input)? {
+        records.push(record.to_vec());
+    }
+    Ok((records, bytes.len() - input.len()))
+}
+
+/// Read the next complete record from `input`, and advance `input` past it. Returns nothing at the
+/// end of `input`, or if the rest of `input` is a single record whose write was interrupted.
+fn next_record<'a>(input: &mut &'a [u8]) -> io::Result<Option<&'a [u8]>> {
+    let Some(header) = input.get(..RECORD_HEADER_LEN) else {
+        return Ok(None);
+    };
+    let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+    let end = RECORD_HEADER_LEN + len;
+    let Some(record) = input.get(RECORD_HEADER_LEN..end) else {
+        return Ok(None);
+    };
+    if hash_bytes(record).0[..4] != header[4..] {
+        if end == input.len() {
+            return Ok(None);
+        }
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "a record in the middle of the log is corrupt",
+        ));
+    }
+    *input = &input[end..];
+    Ok(Some(record))
+}
+
+/// A fresh directory for a test to keep its files in.
+#[cfg(test)]
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mine-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_survive_reopening() {
+        let dir = test_dir("log-reopen");
+        let path = dir.join("test.log");
+
+        let (mut log, records) = Log::open(&path).unwrap();
+        assert!(records.is_empty());
+        log.append(b"hello").unwrap();
+        log.append(b"").unwrap();
+        log.append(b"world").unwrap();
+        drop(log);
+
+        let (mut log, records) = Log::open(&path).unwrap();
+        assert_eq!(records, vec![b"hello".to_vec(), vec![], b"world".to_vec()]);
+
+        log.rewrite(&[b"compacted".to_vec()]).unwrap();
+        log.append(b"again").unwrap();
+        drop(log);
+
+        let (_, records) = Log::open(&path).unwrap();
+        assert_eq!(records, vec![b"compacted".to_vec(), b"again".to_vec()]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn interrupted_write_is_dropped() {
+        let dir = test_dir("log-interrupted");
+        let path = dir.join("test.log");
+
+        let (mut log, _) = Log::open(&path).unwrap();
+        log.append(b"complete").unwrap();
+        let size = log.size();
+        drop(log);
+
+        // Only part of the next record makes it to disk.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&frame(b"interrupted")[..12]).unwrap();
+        drop(file);
+
+        let (mut log, records) = Log::open(&path).unwrap();
+        assert_eq!(records, vec![b"complete".to_vec()]);
+        assert_eq!(log.size(), size);
+
+        log.append(b"next").unwrap();
+        drop(log);
+        let (_, records) = Log::open(&path).unwrap();
+        assert_eq!(records, vec![b"complete".to_vec(), b"next".to_vec()]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_record_before_the_end_is_an_error() {
+        let dir = test_dir("log-corrupt");
+        let path = dir.join("test.log");
+
+        let (mut log, _) = Log::open(&path).unwrap();
+        log.append(b"first").unwrap();
+        log.append(b"second").unwrap();
+        log.append(b"third").unwrap();
+        drop(log);
+
+        // A byte of the second record is flipped on disk.
+        let mut bytes = fs::read(&path).unwrap();
+        let offset = frame(b"first").len() + RECORD_HEADER_LEN;
+        bytes[offset] ^= 0xff;
+        fs::write(&path, &bytes).unwrap();
+
+        let e = Log::open(&path).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+
+        // The same damage to the last record is taken for an interrupted write.
+        bytes.truncate(offset + frame(b"second").len() - RECORD_HEADER_LEN);
+        fs::write(&path, &bytes).unwrap();
+        let (log, records) = Log::open(&path).unwrap();
+        assert_eq!(records, vec![b"first".to_vec()]);
+        assert_eq!(log.size(), frame(b"first").len() as u64);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
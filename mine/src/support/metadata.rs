@@ -0,0 +1,52 @@
+use serde::Serialize;
+
+/// The description of the whole runtime, generated by the macros, so that clients can build calls
+/// without knowing the runtime at compile time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RuntimeMetadata {
+    /// Every pallet with callable functions, in the order they are declared in the runtime.
+    pub pallets: Vec<PalletMetadata>,
+}
+
+/// The description of a pallet. The `name` and `index` are filled in by the runtime, since only it
+/// knows where the pallet lives.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PalletMetadata {
+    /// The name of the pallet in the runtime, which is also the name of its `RuntimeCall` variant.
+    pub name: &'static str,
+    /// The index of the pallet, as used in `DispatchError::Module`.
+    pub index: u8,
+    pub calls: Vec<CallMetadata>,
+    pub errors: Vec<ErrorMetadata>,
+}
+
+/// The description of a callable function.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CallMetadata {
+    pub name: &'static str,
+    /// The index of the function in the `Call` enum of its pallet.
+    pub index: u8,
+    /// The arguments of the function, not including `self` and `origin`.
+    pub args: Vec<ArgMetadata>,
+    /// The doc comments of the function, one entry per line.
+    pub docs: Vec<&'static str>,
+}
+
+/// The description of an argument of a callable function.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ArgMetadata {
+    pub name: &'static str,
+    /// The type of the argument, as written in the pallet's call signature.
+    #[serde(rename = "type")]
+    pub ty: &'static str,
+}
+
+/// The description of a variant of the `Error` enum of a pallet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ErrorMetadata {
+    pub name: &'static str,
+    /// The index of the error, as used in `DispatchError::Module`.
+    pub index: u8,
+    /// The doc comments of the variant, one entry per line.
+    pub docs: Vec<&'static str>,
+}
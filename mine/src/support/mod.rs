@@ -0,0 +1,536 @@
+use codec::Encode;
+use crypto::{Pair, Public, Signature};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use storage::Storage;
+
+pub mod codec;
+pub mod crypto;
+pub mod db;
+pub mod metadata;
+pub mod storage;
+
+/// The most primitive representation of a Blockchain block.
+#[derive(Debug, Clone)]
+pub struct Block<Header, Extrinsic> {
+    /// The block header contains metadata about the block.
+    pub header: Header,
+    /// The extrinsics represent the state transitions to be executed in this block.
+    pub extrinsics: Vec<Extrinsic>,
+}
+
+impl<BlockNumber: Encode, Extrinsic: Encode> Block<Header<BlockNumber>, Extrinsic> {
+    /// The hash of a block is the hash of its header.
+    pub fn hash(&self) -> H256 {
+        self.header.hash()
+    }
+
+    /// The merkle root of the hashes of all the extrinsics in this block.
+    pub fn extrinsics_root(&self) -> H256 {
+        let leaves = self.extrinsics.iter().map(hash_of).collect::<Vec<_>>();
+        merkle_root(&leaves)
+    }
+}
+
+/// The block header links a block to its parent, and commits to the content of the block and to
+/// the state after executing it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Header<BlockNumber> {
+    /// The hash of the header of the previous block.
+    pub parent_hash: H256,
+    /// The number of this block.
+    pub block_number: BlockNumber,
+    /// The state root after executing this block.
+    pub state_root: H256,
+    /// The merkle root of the extrinsics included in this block.
+    pub extrinsics_root: H256,
+    /// The account which authored this block, and is credited with its transaction fees. A block
+    /// may have no author.
+    pub author: Option<Public>,
+}
+
+impl<BlockNumber: Encode> Header<BlockNumber> {
+    /// The hash of this header, which is also the hash of the block.
+    pub fn hash(&self) -> H256 {
+        hash_of(self)
+    }
+}
+
+/// This is an "extrinsic": literally an external message from outside of the blockchain.
+/// This simplified version of an extrinsic tells us which call is being made, and who is making it.
+///
+/// A signed extrinsic is executed on behalf of the account of its signer. An unsigned extrinsic has
+/// no caller, and is only accepted when some pallet explicitly validates it through
+/// `ValidateUnsigned`.
+#[derive(Debug, Clone)]
+pub struct Extrinsic<Nonce, Call> {
+    /// The signer of this extrinsic, or `None` if it is unsigned.
+    pub signature: Option<ExtrinsicSignature<Nonce>>,
+    pub call: Call,
+}
+
+/// Who signed an extrinsic, and the signature proving it.
+///
+/// The `nonce` must match the number of extrinsics the signer has already executed, so that the
+/// same extrinsic cannot be replayed.
+#[derive(Debug, Clone)]
+pub struct ExtrinsicSignature<Nonce> {
+    pub signer: Public,
+    pub nonce: Nonce,
+    /// The signature of `signer` over the signing payload of the extrinsic.
+    pub signature: Signature,
+}
+
+impl<Nonce: Encode, Call: Encode> Extrinsic<Nonce, Call> {
+    /// Create an extrinsic signed by `pair`, for the chain identified by `genesis_hash`.
+    pub fn new_signed(call: Call, nonce: Nonce, pair: &Pair, genesis_hash: H256) -> Self {
+        let payload = Self::signing_payload(&call, &nonce, genesis_hash);
+        let signature = ExtrinsicSignature {
+            signer: pair.public(),
+            nonce,
+            signature: pair.sign(&payload.0),
+        };
+        Extrinsic {
+            signature: Some(signature),
+            call,
+        }
+    }
+
+    /// The message a signer signs. It commits to the call, the nonce, and the chain the extrinsic
+    /// is meant for, so that it cannot be replayed on another chain.
+    pub fn signing_payload(call: &Call, nonce: &Nonce, genesis_hash: H256) -> H256 {
+        hash_of(&(call, nonce, genesis_hash))
+    }
+
+    /// Check the signature of a signed extrinsic. Unsigned extrinsics have nothing to verify, and
+    /// return `false`.
+    pub fn verify(&self, genesis_hash: H256) -> bool {
+        match &self.signature {
+            Some(ExtrinsicSignature {
+                signer,
+                nonce,
+                signature,
+            }) => {
+                let payload = Self::signing_payload(&self.call, nonce, genesis_hash);
+                signer.verify(&payload.0, signature)
+            }
+            None => false,
+        }
+    }
+}
+
+/// A 256 bit hash, used for block hashes and merkle roots.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct H256(pub [u8; 32]);
+
+impl core::fmt::Debug for H256 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write_hex(f, &self.0)
+    }
+}
+
+impl core::str::FromStr for H256 {
+    type Err = &'static str;
+
+    /// Parse a hash from its `0x` prefixed hex representation.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_hex(s).map(H256).ok_or("invalid hash")
+    }
+}
+
+// Hashes are written as hex strings in human readable formats such as JSON.
+impl Serialize for H256 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:?}", self))
+    }
+}
+
+impl<'de> Deserialize<'de> for H256 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Write `bytes` as a `0x` prefixed hex string.
+fn write_hex(f: &mut core::fmt::Formatter<'_>, bytes: &[u8]) -> core::fmt::Result {
+    write!(f, "0x")?;
+    for byte in bytes {
+        write!(f, "{:02x}", byte)?;
+    }
+    Ok(())
+}
+
+/// `bytes` as a `0x` prefixed hex string.
+pub fn to_hex(bytes: &[u8]) -> String {
+    let digits = bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    format!("0x{}", digits)
+}
+
+/// Parse a `0x` prefixed hex string.
+pub fn from_hex(s: &str) -> Option<Vec<u8>> {
+    let digits = s.strip_prefix("0x")?;
+    if digits.len() % 2 != 0 || !digits.is_ascii() {
+        return None;
+    }
+    digits
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(core::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// Parse a `0x` prefixed hex string of exactly `N` bytes.
+pub fn parse_hex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    from_hex(s)?.try_into().ok()
+}
+
+/// Hash some bytes with SHA-256.
+pub fn hash_bytes(bytes: &[u8]) -> H256 {
+    H256(Sha256::digest(bytes).into())
+}
+
+/// Hash the encoding of any value with SHA-256.
+pub fn hash_of<T: Encode + ?Sized>(value: &T) -> H256 {
+    hash_bytes(&value.encode())
+}
+
+/// Compute the root of a binary merkle tree over `leaves`.
+///
+/// Each level is built by hashing pairs of nodes together. When a level has an odd number of
+/// nodes, the last one is carried up to the next level as is. The root of an empty tree is the
+/// zero hash.
+pub fn merkle_root(leaves: &[H256]) -> H256 {
+    if leaves.is_empty() {
+        return H256::default();
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => hash_bytes(&[left.0, right.0].concat()),
+                [single] => *single,
+                _ => unreachable!("chunks are never empty"),
+            })
+            .collect();
+    }
+    level[0]
+}
+
+/// The reason a dispatch failed. Unlike a plain error message, each variant identifies the failure
+/// in a way that callers can match on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum DispatchError {
+    /// An error declared by a pallet's own `Error` enum.
+    Module {
+        /// The position of the pallet in the `Runtime` struct, with `system` at index 0.
+        pallet_index: u8,
+        /// The position of the variant in the pallet's `Error` enum.
+        error_index: u8,
+    },
+    /// The caller is not allowed to make this call.
+    BadOrigin,
+    /// Some checked arithmetic failed.
+    Arithmetic(ArithmeticError),
+    /// Any other error, described by a static message.
+    Other(&'static str),
+}
+
+/// The arithmetic failures which can happen during a dispatch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[allow(dead_code)]
+pub enum ArithmeticError {
+    /// The result is below the minimum value of its type.
+    Underflow,
+    /// The result is above the maximum value of its type.
+    Overflow,
+}
+
+impl DispatchError {
+    /// Set the `pallet_index` of a `Module` error, leaving any other error untouched.
+    ///
+    /// Pallets do not know where they are placed in the `Runtime`, so the runtime dispatch fills
+    /// this in once the call has been routed to a pallet.
+    pub fn with_pallet_index(self, pallet_index: u8) -> Self {
+        match self {
+            DispatchError::Module { error_index, .. } => DispatchError::Module {
+                pallet_index,
+                error_index,
+            },
+            other => other,
+        }
+    }
+}
+
+impl From<ArithmeticError> for DispatchError {
+    fn from(e: ArithmeticError) -> Self {
+        DispatchError::Arithmetic(e)
+    }
+}
+
+impl From<&'static str> for DispatchError {
+    fn from(e: &'static str) -> Self {
+        DispatchError::Other(e)
+    }
+}
+
+/// The Result type for our runtime. When everything completes successfully, we return `Ok(())`,
+/// otherwise return a `DispatchError` describing what went wrong.
+pub type DispatchResult = Result<(), DispatchError>;
+
+/// The cost of executing something in the runtime, in abstract units of execution time. Every
+/// callable function declares its weight, and the total weight of a block is bounded.
+pub type Weight = u64;
+
+/// A trait which allows a pallet to be built from its genesis configuration.
+///
+/// Pallets which have nothing to configure at genesis can use `()` as their `GenesisConfig`.
+pub trait BuildGenesis {
+    /// The initial state of the pallet.
+    type GenesisConfig;
+
+    /// Create an instance of the pallet which keeps its state in `storage`, and write the initial
+    /// state described by `config` there.
+    fn build_genesis(storage: &Storage, config: &Self::GenesisConfig) -> Self;
+}
+
+/// Hooks which the runtime calls on every pallet at the boundaries of each block, in the order the
+/// pallets are declared in the runtime. Every hook does nothing by default.
+pub trait Hooks<BlockNumber> {
+    /// Called at the start of block `n`, before any extrinsic is applied.
+    fn on_initialize(&mut self, _n: BlockNumber) {}
+
+    /// Called after all the extrinsics of block `n` are applied, before `on_finalize`. This is the
+    /// place for optional work which can use up to `remaining_weight`, the weight the block has
+    /// left. Returns the weight it used.
+    fn on_idle(&mut self, _n: BlockNumber, _remaining_weight: Weight) -> Weight {
+        0
+    }
+
+    /// Called at the end of block `n`.
+    fn on_finalize(&mut self, _n: BlockNumber) {}
+}
+
+/// A trait for calls which know their own weight.
+pub trait GetWeight {
+    /// The weight of executing this call.
+    fn weight(&self) -> Weight;
+}
+
+/// A trait which allows a pallet to have the runtime dispatch calls of any pallet, with an origin
+/// of its choosing such as `Root`.
+///
+/// A pallet cannot reach into the rest of the runtime, so it queues the calls instead. Right after
+/// a call of the pallet succeeds, the runtime takes the queued calls and dispatches each of them in
+/// its own storage transaction, then reports the result back to the pallet. By default a pallet
+/// never queues any call.
+pub trait DispatchNested<Origin, Call> {
+    /// Take the calls queued since the last time this was called.
+    fn take_nested_calls(&mut self) -> Vec<(Origin, Call)> {
+        Vec::new()
+    }
+
+    /// Receive the result of a queued call, once it was dispatched.
+    fn on_nested_call_result(&mut self, _result: DispatchResult) {}
+}
+
+/// A trait which allows the runtime to charge a fee for every signed extrinsic, before its call is
+/// dispatched. The fee is kept even if the call fails.
+pub trait ChargeTransaction {
+    type AccountId;
+    type Call;
+
+    /// Withdraw the fee for `call` from `who`, where `len` is the length of the whole extrinsic in
+    /// bytes. An error means `who` cannot pay, which makes the extrinsic invalid.
+    fn charge_transaction(
+        &mut self,
+        who: &Self::AccountId,
+        call: &Self::Call,
+        len: usize,
+    ) -> DispatchResult;
+}
+
+/// A trait which allows a pallet to accept some of its calls without a signature.
+///
+/// Unsigned extrinsics have no caller to hold responsible for them, so by default every call is
+/// rejected. A pallet which wants to accept unsigned calls must explicitly validate them. Valid
+/// unsigned calls are then dispatched with the `None` origin, which the call must also accept.
+pub trait ValidateUnsigned {
+    /// The calls of the pallet.
+    type Call;
+
+    /// Check whether `call` may be included in a block without a signature. A block including an
+    /// invalid unsigned extrinsic is itself invalid.
+    fn validate_unsigned(&self, _call: &Self::Call) -> DispatchResult {
+        Err(DispatchError::BadOrigin)
+    }
+}
+
+/// A trait which allows us to dispatch an incoming extrinsic to the appropriate state transition
+/// function call.
+pub trait Dispatch {
+    /// The type used to identify where the call comes from, such as the signer of an extrinsic.
+    type Origin;
+    /// The state transition function call the origin is trying to access.
+    type Call;
+
+    /// This function takes an `origin` and the `call` it wants to make, and returns a `Result`
+    /// based on the outcome of that function call.
+    fn dispatch(&mut self, origin: Self::Origin, call: Self::Call) -> DispatchResult;
+}
+
+/// A trait for state which keeps part of itself outside of storage, such as the events deposited
+/// by pallets, so that a transaction can roll back that part too.
+pub trait Transactional {
+    /// What it takes to bring the state back to how it was at the checkpoint.
+    type Checkpoint;
+
+    /// Save the state outside of storage, as a transaction starts.
+    fn checkpoint(&mut self) -> Self::Checkpoint;
+
+    /// Bring the state outside of storage back to `checkpoint`, as a transaction fails.
+    fn restore(&mut self, checkpoint: Self::Checkpoint);
+}
+
+/// Execute `f` as a storage transaction over `state`, which keeps its storage in `storage`.
+///
+/// If `f` returns `Err`, every change it made to `storage` and to `state` is discarded, and both are
+/// left as they were before the call. Transactions can be nested: `f` is free to open its own
+/// sub-transaction over `state` (or any part of it), which is rolled back on its own failure
+/// without affecting the outer transaction.
+///
+/// The storage rolls back its own changes. The rest of `state` is restored from a checkpoint.
+pub fn with_transaction<S, R, E>(
+    storage: &Storage,
+    state: &mut S,
+    f: impl FnOnce(&mut S) -> Result<R, E>,
+) -> Result<R, E>
+where
+    S: Transactional,
+{
+    let checkpoint = state.checkpoint();
+    storage.start_transaction();
+    let result = f(state);
+    if result.is_ok() {
+        storage.commit_transaction();
+    } else {
+        storage.rollback_transaction();
+        state.restore(checkpoint);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merkle_root_of_leaves() {
+        let a = hash_bytes(b"a");
+        let b = hash_bytes(b"b");
+        let c = hash_bytes(b"c");
+        let ab = hash_bytes(&[a.0, b.0].concat());
+
+        assert_eq!(merkle_root(&[]), H256::default());
+        assert_eq!(merkle_root(&[a]), a);
+        assert_eq!(merkle_root(&[a, b]), ab);
+        assert_eq!(merkle_root(&[a, b, c]), hash_bytes(&[ab.0, c.0].concat()));
+        assert_ne!(merkle_root(&[b, a]), ab);
+    }
+
+    #[test]
+    fn header_hash_commits_to_all_fields() {
+        let header = Header {
+            parent_hash: H256::default(),
+            block_number: 1u32,
+            state_root: H256::default(),
+            extrinsics_root: H256::default(),
+            author: None,
+        };
+        let mut other = header.clone();
+        other.parent_hash = hash_bytes(b"parent");
+        let mut authored = header.clone();
+        authored.author = Some(Pair::from_name("Alice").public());
+
+        assert_eq!(header.hash(), header.clone().hash());
+        assert_ne!(header.hash(), other.hash());
+        assert_ne!(header.hash(), authored.hash());
+    }
+
+    impl Transactional for Vec<u32> {
+        type Checkpoint = usize;
+
+        fn checkpoint(&mut self) -> usize {
+            self.len()
+        }
+
+        fn restore(&mut self, checkpoint: usize) {
+            self.truncate(checkpoint);
+        }
+    }
+
+    #[test]
+    fn transaction_rolls_back_on_error() {
+        let storage = Storage::default();
+        let value = storage::StorageValue::<u32>::new(&storage, "Test", "Value");
+        let mut state = vec![1, 2, 3];
+
+        let result: DispatchResult = with_transaction(&storage, &mut state, |state| {
+            state.push(4);
+            value.put(&4);
+            Err(DispatchError::Other("failed"))
+        });
+        assert_eq!(result, Err(DispatchError::Other("failed")));
+        assert_eq!(state, vec![1, 2, 3]);
+        assert_eq!(value.get(), None);
+
+        let result: DispatchResult = with_transaction(&storage, &mut state, |state| {
+            state.push(4);
+            value.put(&4);
+            Ok(())
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(state, vec![1, 2, 3, 4]);
+        assert_eq!(value.get(), Some(4));
+    }
+
+    #[test]
+    fn nested_transactions() {
+        let storage = Storage::default();
+        let value = storage::StorageValue::<u32>::new(&storage, "Test", "Value");
+        let mut state = vec![1];
+
+        // The inner transaction fails, but the outer one succeeds.
+        let result: DispatchResult = with_transaction(&storage, &mut state, |state| {
+            state.push(2);
+            value.put(&2);
+            let inner: DispatchResult = with_transaction(&storage, state, |state| {
+                state.push(3);
+                value.put(&3);
+                Err(DispatchError::Other("inner failed"))
+            });
+            assert!(inner.is_err());
+            Ok(())
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(state, vec![1, 2]);
+        assert_eq!(value.get(), Some(2));
+
+        // The inner transaction succeeds, but the outer one fails.
+        let result: DispatchResult = with_transaction(&storage, &mut state, |state| {
+            with_transaction(&storage, state, |state| {
+                state.push(3);
+                value.put(&3);
+                Ok::<(), DispatchError>(())
+            })?;
+            Err(DispatchError::Other("outer failed"))
+        });
+        assert!(result.is_err());
+        assert_eq!(state, vec![1, 2]);
+        assert_eq!(value.get(), Some(2));
+    }
+}
@@ -0,0 +1,759 @@
+// Not every pallet needs every kind of storage item, or every operation on them.
+#![allow(dead_code)]
+
+use super::{
+    codec::{Decode, DecodeError, Encode},
+    db::Log,
+    hash_bytes, hash_of, merkle_root, H256,
+};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    fmt, io,
+    marker::PhantomData,
+    path::Path,
+    rc::Rc,
+};
+
+/// The log of a storage kept on disk is not compacted before it reaches this size in bytes.
+const COMPACTION_MIN_SIZE: u64 = 1 << 16;
+
+/// A handle to the key-value store holding the state of the whole runtime.
+///
+/// Every pallet keeps a handle to the same store, and cloning a handle does not copy the store.
+/// Pallets do not use the store directly, but through the typed `StorageValue`, `StorageMap` and
+/// `StorageDoubleMap` items, which encode their keys under a prefix made of the name of the pallet
+/// and the name of the item.
+///
+/// Changes can be made inside transactions, which are either committed or rolled back as a whole.
+/// Transactions can be nested.
+///
+/// The store is held in memory. A store opened from disk also keeps its entries in an append-only
+/// log, where every `flush` writes the changes made since the previous one.
+#[derive(Clone, Default)]
+pub struct Storage(Rc<RefCell<Backend>>);
+
+#[derive(Default)]
+struct Backend {
+    entries: BTreeMap<Vec<u8>, Vec<u8>>,
+    /// For every open transaction, innermost last, the value each key it changed had before.
+    journals: Vec<BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
+    /// The log holding the entries on disk, if any.
+    log: Option<Log>,
+    /// The keys changed since the last flush, if the entries are kept on disk.
+    dirty: BTreeSet<Vec<u8>>,
+}
+
+/// The changes to some keys, where `None` removes a key. This is what the records of the log of a
+/// storage are made of, and what it takes to revert the changes of a transaction.
+pub type Changes = Vec<(Vec<u8>, Option<Vec<u8>>)>;
+
+impl Storage {
+    /// Open the storage kept on disk in the log at `path`, creating an empty one if needed.
+    pub fn open(path: &Path) -> io::Result<Storage> {
+        let (log, records) = Log::open(path)?;
+        Storage::from_records(records, Some(log))
+    }
+
+    /// Read the storage kept on disk in the log at `path`, which must exist, into a storage which is
+    /// only held in memory. The log is never written to.
+    pub fn open_read_only(path: &Path) -> io::Result<Storage> {
+        Storage::from_records(Log::read(path)?, None)
+    }
+
+    /// The storage holding the entries of the log `records`, kept on disk in `log` if any.
+    fn from_records(records: Vec<Vec<u8>>, log: Option<Log>) -> io::Result<Storage> {
+        let mut entries = BTreeMap::new();
+        for record in records {
+            let changes = Changes::decode_all(&record)
+                .map_err(|DecodeError(e)| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            for (key, value) in changes {
+                match value {
+                    Some(value) => entries.insert(key, value),
+                    None => entries.remove(&key),
+                };
+            }
+        }
+        Ok(Storage(Rc::new(RefCell::new(Backend {
+            entries,
+            log,
+            ..Default::default()
+        }))))
+    }
+
+    /// Write the changes made since the last flush to disk, as a single record of the log. This
+    /// does nothing for a storage which is only held in memory, and cannot be done inside a
+    /// transaction.
+    ///
+    /// Once the log grows to more than twice the size of the entries, it is compacted into a single
+    /// record holding every entry.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut backend = self.0.borrow_mut();
+        let backend = &mut *backend;
+        assert!(
+            backend.journals.is_empty(),
+            "cannot flush inside a transaction"
+        );
+        let Some(log) = &mut backend.log else {
+            return Ok(());
+        };
+        if backend.dirty.is_empty() {
+            return Ok(());
+        }
+
+        let changes = backend
+            .dirty
+            .iter()
+            .map(|key| (key.clone(), backend.entries.get(key).cloned()))
+            .collect::<Changes>();
+        log.append(&changes.encode())?;
+        backend.dirty.clear();
+
+        let entries_size = backend
+            .entries
+            .iter()
+            .map(|(key, value)| (key.len() + value.len()) as u64)
+            .sum::<u64>();
+        if log.size() > COMPACTION_MIN_SIZE && log.size() > 2 * entries_size {
+            let changes = backend
+                .entries
+                .iter()
+                .map(|(key, value)| (key.clone(), Some(value.clone())))
+                .collect::<Changes>();
+            log.rewrite(&[changes.encode()])?;
+        }
+        Ok(())
+    }
+
+    /// Whether the storage has no entries at all.
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().entries.is_empty()
+    }
+
+    /// Get the value of `key`, if any.
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.borrow().entries.get(key).cloned()
+    }
+
+    /// Set the value of `key`, or remove it if `value` is `None`.
+    pub fn set(&self, key: &[u8], value: Option<Vec<u8>>) {
+        let mut backend = self.0.borrow_mut();
+        let old = match value {
+            Some(value) => backend.entries.insert(key.to_vec(), value),
+            None => backend.entries.remove(key),
+        };
+        if let Some(journal) = backend.journals.last_mut() {
+            journal.entry(key.to_vec()).or_insert(old);
+        }
+        if backend.log.is_some() {
+            backend.dirty.insert(key.to_vec());
+        }
+    }
+
+    /// All the entries whose key starts with `prefix`, in key order.
+    pub fn iter_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.0
+            .borrow()
+            .entries
+            .range(prefix.to_vec()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    /// The merkle root of all the entries, in key order.
+    pub fn root(&self) -> H256 {
+        let leaves = self
+            .0
+            .borrow()
+            .entries
+            .iter()
+            .map(|entry| hash_of(&entry))
+            .collect::<Vec<_>>();
+        merkle_root(&leaves)
+    }
+
+    /// Open a new transaction, nested in the current one if any.
+    pub fn start_transaction(&self) {
+        self.0.borrow_mut().journals.push(BTreeMap::new());
+    }
+
+    /// Keep the changes of the innermost transaction. They become part of the enclosing
+    /// transaction, if any.
+    pub fn commit_transaction(&self) {
+        let mut backend = self.0.borrow_mut();
+        let journal = backend.journals.pop().expect("no open transaction");
+        if let Some(parent) = backend.journals.last_mut() {
+            for (key, old) in journal {
+                parent.entry(key).or_insert(old);
+            }
+        }
+    }
+
+    /// Keep the changes of the innermost transaction, like `commit_transaction`, and return the
+    /// changes which `revert` needs to undo them later on.
+    pub fn commit_transaction_with_undo(&self) -> Changes {
+        let undo = self
+            .0
+            .borrow()
+            .journals
+            .last()
+            .expect("no open transaction")
+            .iter()
+            .map(|(key, old)| (key.clone(), old.clone()))
+            .collect();
+        self.commit_transaction();
+        undo
+    }
+
+    /// Undo the changes of a committed transaction, given what `commit_transaction_with_undo`
+    /// returned. Any change made after the transaction to the same keys must be undone first.
+    pub fn revert(&self, undo: &Changes) {
+        for (key, old) in undo {
+            self.set(key, old.clone());
+        }
+    }
+
+    /// Discard the changes of the innermost transaction.
+    pub fn rollback_transaction(&self) {
+        let mut backend = self.0.borrow_mut();
+        let journal = backend.journals.pop().expect("no open transaction");
+        for (key, old) in journal {
+            match old {
+                Some(value) => backend.entries.insert(key, value),
+                None => backend.entries.remove(&key),
+            };
+        }
+    }
+}
+
+impl fmt::Debug for Storage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let backend = self.0.borrow();
+        f.debug_struct("Storage")
+            .field("entries", &backend.entries.len())
+            .field("transactions", &backend.journals.len())
+            .field("on_disk", &backend.log.is_some())
+            .finish()
+    }
+}
+
+/// The prefix of all the keys of the item `item` of the pallet `pallet`.
+///
+/// Both names are hashed to a fixed length, so the keys of an item never share a prefix with the
+/// keys of another item.
+fn item_prefix(pallet: &str, item: &str) -> Vec<u8> {
+    [
+        &hash_bytes(pallet.as_bytes()).0[..16],
+        &hash_bytes(item.as_bytes()).0[..16],
+    ]
+    .concat()
+}
+
+/// Decode a value read from storage. Only values of the right type are ever written under the key
+/// of an item, so this never fails.
+fn decode<T: Decode>(bytes: &[u8]) -> T {
+    T::decode_all(bytes).expect("storage holds a value of another type")
+}
+
+/// A single value in storage.
+pub struct StorageValue<V> {
+    storage: Storage,
+    key: Vec<u8>,
+    _value: PhantomData<V>,
+}
+
+impl<V: Encode + Decode> StorageValue<V> {
+    /// The item `item` of the pallet `pallet`, kept in `storage`.
+    pub fn new(storage: &Storage, pallet: &str, item: &str) -> Self {
+        Self {
+            storage: storage.clone(),
+            key: item_prefix(pallet, item),
+            _value: PhantomData,
+        }
+    }
+
+    /// Get the value, if any.
+    pub fn get(&self) -> Option<V> {
+        self.storage.get(&self.key).map(|bytes| decode(&bytes))
+    }
+
+    /// Whether there is a value.
+    pub fn exists(&self) -> bool {
+        self.storage.get(&self.key).is_some()
+    }
+
+    /// Set the value.
+    pub fn put(&self, value: &V) {
+        self.storage.set(&self.key, Some(value.encode()));
+    }
+
+    /// Remove the value.
+    pub fn kill(&self) {
+        self.storage.set(&self.key, None);
+    }
+
+    /// Remove the value, and return it.
+    pub fn take(&self) -> Option<V> {
+        let value = self.get();
+        self.kill();
+        value
+    }
+
+    /// Change the value in place. Setting it to `None` removes it.
+    pub fn mutate<R>(&self, f: impl FnOnce(&mut Option<V>) -> R) -> R {
+        let mut value = self.get();
+        let result = f(&mut value);
+        self.storage
+            .set(&self.key, value.map(|value| value.encode()));
+        result
+    }
+
+    /// Change the value in place, only if `f` returns `Ok`.
+    pub fn try_mutate<R, E>(&self, f: impl FnOnce(&mut Option<V>) -> Result<R, E>) -> Result<R, E> {
+        let mut value = self.get();
+        let result = f(&mut value)?;
+        self.storage
+            .set(&self.key, value.map(|value| value.encode()));
+        Ok(result)
+    }
+}
+
+impl<V> Clone for StorageValue<V> {
+    fn clone(&self) -> Self {
+        Self {
+            storage: self.storage.clone(),
+            key: self.key.clone(),
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<V: Encode + Decode + fmt::Debug> fmt::Debug for StorageValue<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.get().fmt(f)
+    }
+}
+
+/// A map in storage, from keys of type `K` to values of type `V`.
+///
+/// The storage key of an entry is the prefix of the item followed by the encoding of `K`, so
+/// entries are iterated in the order of their encoded keys.
+pub struct StorageMap<K, V> {
+    storage: Storage,
+    prefix: Vec<u8>,
+    _entry: PhantomData<(K, V)>,
+}
+
+impl<K: Encode, V: Encode + Decode> StorageMap<K, V> {
+    /// The item `item` of the pallet `pallet`, kept in `storage`.
+    pub fn new(storage: &Storage, pallet: &str, item: &str) -> Self {
+        Self {
+            storage: storage.clone(),
+            prefix: item_prefix(pallet, item),
+            _entry: PhantomData,
+        }
+    }
+
+    fn storage_key(&self, key: &K) -> Vec<u8> {
+        let mut storage_key = self.prefix.clone();
+        key.encode_to(&mut storage_key);
+        storage_key
+    }
+
+    /// Get the value of `key`, if any.
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.storage
+            .get(&self.storage_key(key))
+            .map(|bytes| decode(&bytes))
+    }
+
+    /// Whether there is a value for `key`.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.storage.get(&self.storage_key(key)).is_some()
+    }
+
+    /// Set the value of `key`.
+    pub fn insert(&self, key: &K, value: &V) {
+        self.storage
+            .set(&self.storage_key(key), Some(value.encode()));
+    }
+
+    /// Remove the value of `key`.
+    pub fn remove(&self, key: &K) {
+        self.storage.set(&self.storage_key(key), None);
+    }
+
+    /// Remove the value of `key`, and return it.
+    pub fn take(&self, key: &K) -> Option<V> {
+        let value = self.get(key);
+        self.remove(key);
+        value
+    }
+
+    /// Change the value of `key` in place. Setting it to `None` removes it.
+    pub fn mutate<R>(&self, key: &K, f: impl FnOnce(&mut Option<V>) -> R) -> R {
+        let storage_key = self.storage_key(key);
+        let mut value = self.storage.get(&storage_key).map(|bytes| decode(&bytes));
+        let result = f(&mut value);
+        self.storage
+            .set(&storage_key, value.map(|value| value.encode()));
+        result
+    }
+
+    /// Change the value of `key` in place, only if `f` returns `Ok`.
+    pub fn try_mutate<R, E>(
+        &self,
+        key: &K,
+        f: impl FnOnce(&mut Option<V>) -> Result<R, E>,
+    ) -> Result<R, E> {
+        let storage_key = self.storage_key(key);
+        let mut value = self.storage.get(&storage_key).map(|bytes| decode(&bytes));
+        let result = f(&mut value)?;
+        self.storage
+            .set(&storage_key, value.map(|value| value.encode()));
+        Ok(result)
+    }
+
+    /// All the entries of the map, in the order of their encoded keys.
+    pub fn iter(&self) -> impl Iterator<Item = (K, V)>
+    where
+        K: Decode,
+    {
+        let prefix_len = self.prefix.len();
+        self.storage
+            .iter_prefix(&self.prefix)
+            .into_iter()
+            .map(move |(key, value)| (decode(&key[prefix_len..]), decode(&value)))
+    }
+}
+
+impl<K, V> Clone for StorageMap<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            storage: self.storage.clone(),
+            prefix: self.prefix.clone(),
+            _entry: PhantomData,
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for StorageMap<K, V>
+where
+    K: Encode + Decode + fmt::Debug,
+    V: Encode + Decode + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// A map in storage with two keys, from `(K1, K2)` to values of type `V`.
+///
+/// The storage key of an entry is the prefix of the item followed by the encodings of `K1` and
+/// `K2`, so all the entries sharing the same `K1` can be iterated or removed together.
+pub struct StorageDoubleMap<K1, K2, V> {
+    storage: Storage,
+    prefix: Vec<u8>,
+    _entry: PhantomData<(K1, K2, V)>,
+}
+
+impl<K1: Encode, K2: Encode, V: Encode + Decode> StorageDoubleMap<K1, K2, V> {
+    /// The item `item` of the pallet `pallet`, kept in `storage`.
+    pub fn new(storage: &Storage, pallet: &str, item: &str) -> Self {
+        Self {
+            storage: storage.clone(),
+            prefix: item_prefix(pallet, item),
+            _entry: PhantomData,
+        }
+    }
+
+    fn first_key_prefix(&self, key1: &K1) -> Vec<u8> {
+        let mut prefix = self.prefix.clone();
+        key1.encode_to(&mut prefix);
+        prefix
+    }
+
+    fn storage_key(&self, key1: &K1, key2: &K2) -> Vec<u8> {
+        let mut storage_key = self.first_key_prefix(key1);
+        key2.encode_to(&mut storage_key);
+        storage_key
+    }
+
+    /// Get the value of `(key1, key2)`, if any.
+    pub fn get(&self, key1: &K1, key2: &K2) -> Option<V> {
+        self.storage
+            .get(&self.storage_key(key1, key2))
+            .map(|bytes| decode(&bytes))
+    }
+
+    /// Whether there is a value for `(key1, key2)`.
+    pub fn contains_key(&self, key1: &K1, key2: &K2) -> bool {
+        self.storage.get(&self.storage_key(key1, key2)).is_some()
+    }
+
+    /// Set the value of `(key1, key2)`.
+    pub fn insert(&self, key1: &K1, key2: &K2, value: &V) {
+        self.storage
+            .set(&self.storage_key(key1, key2), Some(value.encode()));
+    }
+
+    /// Remove the value of `(key1, key2)`.
+    pub fn remove(&self, key1: &K1, key2: &K2) {
+        self.storage.set(&self.storage_key(key1, key2), None);
+    }
+
+    /// Remove the value of `(key1, key2)`, and return it.
+    pub fn take(&self, key1: &K1, key2: &K2) -> Option<V> {
+        let value = self.get(key1, key2);
+        self.remove(key1, key2);
+        value
+    }
+
+    /// Change the value of `(key1, key2)` in place. Setting it to `None` removes it.
+    pub fn mutate<R>(&self, key1: &K1, key2: &K2, f: impl FnOnce(&mut Option<V>) -> R) -> R {
+        let storage_key = self.storage_key(key1, key2);
+        let mut value = self.storage.get(&storage_key).map(|bytes| decode(&bytes));
+        let result = f(&mut value);
+        self.storage
+            .set(&storage_key, value.map(|value| value.encode()));
+        result
+    }
+
+    /// Change the value of `(key1, key2)` in place, only if `f` returns `Ok`.
+    pub fn try_mutate<R, E>(
+        &self,
+        key1: &K1,
+        key2: &K2,
+        f: impl FnOnce(&mut Option<V>) -> Result<R, E>,
+    ) -> Result<R, E> {
+        let storage_key = self.storage_key(key1, key2);
+        let mut value = self.storage.get(&storage_key).map(|bytes| decode(&bytes));
+        let result = f(&mut value)?;
+        self.storage
+            .set(&storage_key, value.map(|value| value.encode()));
+        Ok(result)
+    }
+
+    /// All the entries whose first key is `key1`, in the order of their encoded second keys.
+    pub fn iter_prefix(&self, key1: &K1) -> impl Iterator<Item = (K2, V)>
+    where
+        K2: Decode,
+    {
+        let prefix = self.first_key_prefix(key1);
+        self.storage
+            .iter_prefix(&prefix)
+            .into_iter()
+            .map(move |(key, value)| (decode(&key[prefix.len()..]), decode(&value)))
+    }
+
+    /// Remove all the entries whose first key is `key1`.
+    pub fn remove_prefix(&self, key1: &K1) {
+        for (key, _) in self.storage.iter_prefix(&self.first_key_prefix(key1)) {
+            self.storage.set(&key, None);
+        }
+    }
+
+    /// All the entries of the map, in the order of their encoded keys.
+    pub fn iter(&self) -> impl Iterator<Item = (K1, K2, V)>
+    where
+        K1: Decode,
+        K2: Decode,
+    {
+        let prefix_len = self.prefix.len();
+        self.storage
+            .iter_prefix(&self.prefix)
+            .into_iter()
+            .map(move |(key, value)| {
+                let mut input = &key[prefix_len..];
+                let key1 = K1::decode(&mut input).expect("storage holds a key of another type");
+                (key1, decode(input), decode(&value))
+            })
+    }
+}
+
+impl<K1, K2, V> Clone for StorageDoubleMap<K1, K2, V> {
+    fn clone(&self) -> Self {
+        Self {
+            storage: self.storage.clone(),
+            prefix: self.prefix.clone(),
+            _entry: PhantomData,
+        }
+    }
+}
+
+impl<K1, K2, V> fmt::Debug for StorageDoubleMap<K1, K2, V>
+where
+    K1: Encode + Decode + fmt::Debug,
+    K2: Encode + Decode + fmt::Debug,
+    V: Encode + Decode + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(self.iter().map(|(key1, key2, value)| ((key1, key2), value)))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn items_are_prefixed() {
+        let storage = Storage::default();
+        let value = StorageValue::<u32>::new(&storage, "Pallet", "Value");
+        let map = StorageMap::<u32, u64>::new(&storage, "Pallet", "Map");
+        let other = StorageMap::<u32, u64>::new(&storage, "Other", "Map");
+
+        value.put(&1);
+        map.insert(&1, &10);
+        map.insert(&2, &20);
+        other.insert(&1, &100);
+
+        assert_eq!(value.get(), Some(1));
+        assert_eq!(map.get(&1), Some(10));
+        assert_eq!(other.get(&1), Some(100));
+        assert_eq!(map.iter().collect::<Vec<_>>(), vec![(1, 10), (2, 20)]);
+        assert_eq!(other.iter().collect::<Vec<_>>(), vec![(1, 100)]);
+
+        // Every item lives in the same backend.
+        let root = storage.root();
+        map.remove(&2);
+        assert!(!map.contains_key(&2));
+        assert_ne!(storage.root(), root);
+        map.insert(&2, &20);
+        assert_eq!(storage.root(), root);
+    }
+
+    #[test]
+    fn mutate_and_take() {
+        let storage = Storage::default();
+        let value = StorageValue::<u32>::new(&storage, "Pallet", "Value");
+        let map = StorageMap::<u32, u64>::new(&storage, "Pallet", "Map");
+
+        value.mutate(|value| *value = Some(value.unwrap_or(0) + 1));
+        assert_eq!(value.get(), Some(1));
+        assert_eq!(value.take(), Some(1));
+        assert!(!value.exists());
+
+        let result: Result<(), &str> = map.try_mutate(&1, |value| {
+            *value = Some(10);
+            Err("failed")
+        });
+        assert_eq!(result, Err("failed"));
+        assert_eq!(map.get(&1), None);
+
+        let result: Result<u64, &str> = map.try_mutate(&1, |value| {
+            *value = Some(10);
+            Ok(10)
+        });
+        assert_eq!(result, Ok(10));
+        assert_eq!(map.get(&1), Some(10));
+
+        // Mutating to `None` removes the entry.
+        map.mutate(&1, |value| *value = None);
+        assert_eq!(map.take(&1), None);
+        assert_eq!(storage.root(), H256::default());
+    }
+
+    #[test]
+    fn double_map_prefix() {
+        let storage = Storage::default();
+        let map = StorageDoubleMap::<u32, u32, bool>::new(&storage, "Pallet", "DoubleMap");
+
+        map.insert(&1, &1, &true);
+        map.insert(&1, &2, &false);
+        map.insert(&2, &1, &true);
+
+        assert_eq!(map.get(&1, &2), Some(false));
+        assert_eq!(
+            map.iter_prefix(&1).collect::<Vec<_>>(),
+            vec![(1, true), (2, false)]
+        );
+
+        map.remove_prefix(&1);
+        assert_eq!(map.iter().collect::<Vec<_>>(), vec![(2, 1, true)]);
+    }
+
+    #[test]
+    fn nested_transactions() {
+        let storage = Storage::default();
+        let map = StorageMap::<u32, u32>::new(&storage, "Pallet", "Map");
+        map.insert(&1, &1);
+
+        storage.start_transaction();
+        map.insert(&1, &2);
+        map.insert(&2, &2);
+
+        storage.start_transaction();
+        map.remove(&1);
+        storage.rollback_transaction();
+        assert_eq!(map.get(&1), Some(2));
+
+        storage.start_transaction();
+        map.insert(&3, &3);
+        storage.commit_transaction();
+
+        // Committed changes of a nested transaction are rolled back with the outer transaction.
+        storage.rollback_transaction();
+        assert_eq!(map.iter().collect::<Vec<_>>(), vec![(1, 1)]);
+    }
+
+    #[test]
+    fn committed_changes_can_be_reverted() {
+        let storage = Storage::default();
+        let map = StorageMap::<u32, u32>::new(&storage, "Pallet", "Map");
+        map.insert(&1, &1);
+        map.insert(&2, &2);
+        let root = storage.root();
+
+        storage.start_transaction();
+        map.insert(&1, &10);
+        map.remove(&2);
+        map.insert(&3, &3);
+        let undo = storage.commit_transaction_with_undo();
+        storage.start_transaction();
+        map.insert(&3, &30);
+        let undo_next = storage.commit_transaction_with_undo();
+
+        storage.revert(&undo_next);
+        assert_eq!(map.get(&3), Some(3));
+        storage.revert(&undo);
+        assert_eq!(map.iter().collect::<Vec<_>>(), vec![(1, 1), (2, 2)]);
+        assert_eq!(storage.root(), root);
+    }
+
+    #[test]
+    fn storage_on_disk() {
+        let dir = super::super::db::test_dir("storage-on-disk");
+        let path = dir.join("state.log");
+
+        let storage = Storage::open(&path).unwrap();
+        let map = StorageMap::<u32, Vec<u8>>::new(&storage, "Pallet", "Map");
+        map.insert(&1, &vec![1; 100]);
+        map.insert(&2, &vec![2; 100]);
+        storage.flush().unwrap();
+
+        // Changes which are not flushed are lost.
+        map.remove(&1);
+        drop((storage, map));
+        let storage = Storage::open(&path).unwrap();
+        let map = StorageMap::<u32, Vec<u8>>::new(&storage, "Pallet", "Map");
+        assert_eq!(map.get(&1), Some(vec![1; 100]));
+
+        // Overwriting the same entries over and over eventually compacts the log.
+        map.remove(&1);
+        for i in 0..1_000u32 {
+            map.insert(&2, &vec![i as u8; 100]);
+            storage.flush().unwrap();
+        }
+        assert!(std::fs::metadata(&path).unwrap().len() < COMPACTION_MIN_SIZE);
+        let root = storage.root();
+        drop((storage, map));
+
+        let storage = Storage::open(&path).unwrap();
+        assert_eq!(storage.root(), root);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,48 +1,305 @@
+use crate::support::{
+    codec::{Decode, Encode},
+    storage::{Storage, StorageMap, StorageValue},
+    BuildGenesis, DispatchError, Hooks, Weight, H256,
+};
+use core::fmt::Debug;
 use num::{One, Zero};
-use std::{collections::BTreeMap, ops::AddAssign};
+use serde::{Deserialize, Serialize};
+use std::ops::AddAssign;
 
 pub trait Config {
     /// Address/pointer to on chain data
-    type AccountId: Ord + Clone;
+    type AccountId: Ord + Clone + Encode + Decode;
 
     /// Incremental
-    type BlockNumber: Zero + One + AddAssign + Copy;
+    type BlockNumber: Zero + One + AddAssign + Copy + Encode + Decode;
 
     /// Incremental
-    type Nonce: Zero + One + AddAssign;
+    type Nonce: Zero + One + AddAssign + Clone + Encode + Decode + PartialEq;
+
+    /// The aggregate of the events of every pallet in the runtime
+    type RuntimeEvent: Debug + Clone;
+
+    /// The aggregate of the calls of every pallet in the runtime
+    type RuntimeCall: Debug + Clone;
+
+    /// Where a call comes from, which can be turned back into a `RawOrigin` when it is one
+    type RuntimeOrigin: Debug
+        + Clone
+        + From<RawOrigin<Self::AccountId>>
+        + Into<Result<RawOrigin<Self::AccountId>, Self::RuntimeOrigin>>;
+
+    /// The maximum total weight of the extrinsics in a block
+    const MAX_BLOCK_WEIGHT: Weight;
+}
+
+/// The origins a call can be dispatched with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RawOrigin<AccountId> {
+    /// The system itself, with the highest privilege. No extrinsic can be dispatched as root
+    /// directly.
+    Root,
+    /// A signed extrinsic from this account.
+    Signed(AccountId),
+    /// An unsigned extrinsic, which was accepted by its pallet through `ValidateUnsigned`.
+    None,
+}
+
+// Lets `RawOrigin` be used as the `RuntimeOrigin` of a runtime with no other origins.
+impl<AccountId> From<RawOrigin<AccountId>> for Result<RawOrigin<AccountId>, RawOrigin<AccountId>> {
+    fn from(origin: RawOrigin<AccountId>) -> Self {
+        Ok(origin)
+    }
+}
+
+/// Ensure that `origin` is a signed account, and return that account.
+pub fn ensure_signed<OuterOrigin, AccountId>(
+    origin: OuterOrigin,
+) -> Result<AccountId, DispatchError>
+where
+    OuterOrigin: Into<Result<RawOrigin<AccountId>, OuterOrigin>>,
+{
+    match origin.into() {
+        Ok(RawOrigin::Signed(who)) => Ok(who),
+        _ => Err(DispatchError::BadOrigin),
+    }
+}
+
+/// Ensure that `origin` is root.
+pub fn ensure_root<OuterOrigin, AccountId>(origin: OuterOrigin) -> Result<(), DispatchError>
+where
+    OuterOrigin: Into<Result<RawOrigin<AccountId>, OuterOrigin>>,
+{
+    match origin.into() {
+        Ok(RawOrigin::Root) => Ok(()),
+        _ => Err(DispatchError::BadOrigin),
+    }
+}
+
+/// The events deposited by the System Pallet itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Event {
+    /// An extrinsic was dispatched successfully.
+    ExtrinsicSuccess,
+    /// An extrinsic failed to dispatch. Its changes were rolled back.
+    ExtrinsicFailed { error: DispatchError },
+}
+
+/// The point in the execution of a block at which an event was deposited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Phase {
+    /// During the `on_initialize` hooks, before any extrinsic is applied.
+    Initialization,
+    /// While applying the extrinsic at this index in the block.
+    ApplyExtrinsic(u32),
+    /// During the `on_idle` and `on_finalize` hooks, after all extrinsics are applied.
+    Finalization,
+}
+
+/// An event, along with when it happened in the block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EventRecord<RuntimeEvent> {
+    pub phase: Phase,
+    pub event: RuntimeEvent,
+}
+
+/// The state of the System Pallet which is not kept in storage, as it was when a transaction
+/// started. See `Pallet::checkpoint`.
+#[derive(Debug, Clone)]
+pub struct Checkpoint<T: Config> {
+    genesis_hash: H256,
+    parent_hash: H256,
+    author: Option<T::AccountId>,
+    phase: Phase,
+    events: usize,
+}
+
+/// The initial state of the System Pallet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "T::BlockNumber: Serialize",
+    deserialize = "T::BlockNumber: Deserialize<'de>"
+))]
+pub struct GenesisConfig<T: Config> {
+    /// The number of the genesis block. The first executed block is the one after it.
+    pub block_number: T::BlockNumber,
 }
 
-#[derive(Debug)]
+impl<T: Config> Default for GenesisConfig<T> {
+    fn default() -> Self {
+        GenesisConfig {
+            block_number: T::BlockNumber::zero(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 /// This is the System Pallet.
 /// It handles low level state needed for your blockchain.
 pub struct Pallet<T: Config> {
-    block_number: T::BlockNumber,
-    nonce: BTreeMap<T::AccountId, T::Nonce>,
+    /// The storage of the whole runtime, which every pallet shares.
+    storage: Storage,
+    block_number: StorageValue<T::BlockNumber>,
+    /// The hash of the genesis header, which identifies the chain.
+    /// Like `parent_hash`, this is not part of the state root.
+    genesis_hash: H256,
+    /// The hash of the header of the last executed block.
+    /// This is not part of the state root, since the header itself commits to the state root.
+    parent_hash: H256,
+    /// The author of the current block, taken from its header. Like `parent_hash`, this is not
+    /// part of the state root.
+    author: Option<T::AccountId>,
+    nonce: StorageMap<T::AccountId, T::Nonce>,
+    /// The phase of the current block which the runtime is in, which new events belong to.
+    phase: Phase,
+    /// The events deposited during the current block. They are cleared at the start of every
+    /// block, and are not part of the state root.
+    events: Vec<EventRecord<T::RuntimeEvent>>,
 }
 
 impl<T: Config> Pallet<T> {
-    /// Create a new instance of the System Pallet.
-    pub fn new() -> Self {
+    /// Create a new instance of the System Pallet, which keeps its state in `storage`.
+    pub fn new(storage: &Storage) -> Self {
         Pallet {
-            block_number: T::BlockNumber::zero(),
-            nonce: BTreeMap::new(),
+            storage: storage.clone(),
+            block_number: StorageValue::new(storage, "System", "BlockNumber"),
+            genesis_hash: H256::default(),
+            parent_hash: H256::default(),
+            author: None,
+            nonce: StorageMap::new(storage, "System", "Nonce"),
+            phase: Phase::Initialization,
+            events: Vec::new(),
         }
     }
 
+    /// Get the storage of the whole runtime
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
     /// Get current block number
     pub fn block_number(&self) -> T::BlockNumber {
-        self.block_number
+        self.block_number.get().unwrap_or(T::BlockNumber::zero())
+    }
+
+    /// Get the block number expected for the next block
+    pub fn next_block_number(&self) -> T::BlockNumber {
+        self.block_number() + T::BlockNumber::one()
     }
 
     /// Increment block number by one
     pub fn inc_block_number(&mut self) {
-        self.block_number += T::BlockNumber::one();
+        self.block_number.put(&self.next_block_number());
+    }
+
+    /// Get the hash of the genesis header, which extrinsics are signed for
+    pub fn genesis_hash(&self) -> H256 {
+        self.genesis_hash
+    }
+
+    /// Record the hash of the genesis header
+    pub fn set_genesis_hash(&mut self, hash: H256) {
+        self.genesis_hash = hash;
+    }
+
+    /// Get the hash of the header of the last executed block, which the next block must build on.
+    /// Before the first block, this is the zero hash.
+    pub fn parent_hash(&self) -> H256 {
+        self.parent_hash
+    }
+
+    /// Record the hash of the header of the block which was just executed
+    pub fn set_parent_hash(&mut self, hash: H256) {
+        self.parent_hash = hash;
+    }
+
+    /// Get the author of the current block, if it has one
+    pub fn block_author(&self) -> Option<&T::AccountId> {
+        self.author.as_ref()
+    }
+
+    /// Record the author of the block which is being executed
+    pub fn set_block_author(&mut self, author: Option<T::AccountId>) {
+        self.author = author;
+    }
+
+    /// Get the nonce of account `who` (defaults to 0), which is also the nonce expected for its
+    /// next extrinsic
+    pub fn nonce(&self, who: &T::AccountId) -> T::Nonce {
+        self.nonce.get(who).unwrap_or(T::Nonce::zero())
+    }
+
+    /// Get the phase of the current block which the runtime is in
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// Record the phase of the current block which the runtime enters
+    pub fn set_phase(&mut self, phase: Phase) {
+        self.phase = phase;
+    }
+
+    /// Get the events deposited so far in the current block
+    pub fn events(&self) -> &[EventRecord<T::RuntimeEvent>] {
+        &self.events
+    }
+
+    /// Save the state of this pallet which is not kept in storage, so that a failed transaction
+    /// can restore it. This is cheap: only the number of events is kept, not the events.
+    pub fn checkpoint(&self) -> Checkpoint<T> {
+        Checkpoint {
+            genesis_hash: self.genesis_hash,
+            parent_hash: self.parent_hash,
+            author: self.author.clone(),
+            phase: self.phase,
+            events: self.events.len(),
+        }
+    }
+
+    /// Restore the state saved by `checkpoint`. The events deposited since are dropped, but the
+    /// events cleared since, by the start of a new block, are not brought back.
+    pub fn restore(&mut self, checkpoint: Checkpoint<T>) {
+        self.genesis_hash = checkpoint.genesis_hash;
+        self.parent_hash = checkpoint.parent_hash;
+        self.author = checkpoint.author;
+        self.phase = checkpoint.phase;
+        self.events.truncate(checkpoint.events);
+    }
+
+    /// Deposit an event which happened during `phase` of the current block
+    pub fn deposit_event(&mut self, phase: Phase, event: T::RuntimeEvent) {
+        self.events.push(EventRecord { phase, event });
+    }
+
+    /// Clear the events of the previous block
+    pub fn reset_events(&mut self) {
+        self.events.clear();
     }
 
     // Increment an account's nonce
     pub fn inc_nonce(&mut self, who: &T::AccountId) {
-        let nonce = self.nonce.entry(who.clone()).or_insert(T::Nonce::zero());
-        *nonce += T::Nonce::one();
+        self.nonce.mutate(who, |nonce| {
+            *nonce.get_or_insert(T::Nonce::zero()) += T::Nonce::one();
+        });
+    }
+}
+
+impl<T: Config> BuildGenesis for Pallet<T> {
+    type GenesisConfig = GenesisConfig<T>;
+
+    fn build_genesis(storage: &Storage, config: &GenesisConfig<T>) -> Self {
+        let pallet = Self::new(storage);
+        pallet.block_number.put(&config.block_number);
+        pallet
+    }
+}
+
+impl<T: Config> Hooks<T::BlockNumber> for Pallet<T> {
+    /// The events of the previous block are cleared at the start of every block. Since system is
+    /// the first pallet in the runtime, this happens before any other pallet can deposit an event.
+    fn on_initialize(&mut self, _n: T::BlockNumber) {
+        self.reset_events();
     }
 }
 
@@ -55,25 +312,96 @@ mod tests {
         type AccountId = String;
         type BlockNumber = u32;
         type Nonce = u32;
+        type RuntimeEvent = Event;
+        type RuntimeCall = ();
+        type RuntimeOrigin = RawOrigin<String>;
+        const MAX_BLOCK_WEIGHT: u64 = 1_000;
     }
 
     #[test]
     fn init_system() {
-        let mut pallet = Pallet::<TestConfig>::new();
+        let mut pallet = Pallet::<TestConfig>::new(&Storage::default());
 
         assert_eq!(pallet.block_number(), 0);
+        assert_eq!(pallet.next_block_number(), 1);
         pallet.inc_block_number();
         assert_eq!(pallet.block_number(), 1);
+        assert_eq!(pallet.next_block_number(), 2);
+    }
+
+    #[test]
+    fn build_genesis() {
+        let pallet = Pallet::<TestConfig>::build_genesis(
+            &Storage::default(),
+            &GenesisConfig { block_number: 10 },
+        );
+        assert_eq!(pallet.block_number(), 10);
+        assert_eq!(pallet.next_block_number(), 11);
     }
 
     #[test]
     fn init_nonce() {
-        let mut pallet = Pallet::<TestConfig>::new();
+        let mut pallet = Pallet::<TestConfig>::new(&Storage::default());
 
         assert_eq!(pallet.nonce.get(&"Alice".to_string()), None);
+        assert_eq!(pallet.nonce(&"Alice".to_string()), 0);
         pallet.inc_nonce(&"Alice".to_string());
-        assert_eq!(pallet.nonce.get(&"Alice".to_string()), Some(&1));
+        assert_eq!(pallet.nonce.get(&"Alice".to_string()), Some(1));
         pallet.inc_nonce(&"Alice".to_string());
-        assert_eq!(pallet.nonce.get(&"Alice".to_string()), Some(&2));
+        assert_eq!(pallet.nonce.get(&"Alice".to_string()), Some(2));
+        assert_eq!(pallet.nonce(&"Alice".to_string()), 2);
+        assert_eq!(pallet.nonce(&"Bob".to_string()), 0);
+    }
+
+    #[test]
+    fn events() {
+        let mut pallet = Pallet::<TestConfig>::new(&Storage::default());
+        assert_eq!(pallet.events(), &[]);
+
+        pallet.deposit_event(Phase::ApplyExtrinsic(0), Event::ExtrinsicSuccess);
+        pallet.deposit_event(
+            Phase::ApplyExtrinsic(1),
+            Event::ExtrinsicFailed {
+                error: DispatchError::BadOrigin,
+            },
+        );
+        assert_eq!(
+            pallet.events(),
+            &[
+                EventRecord {
+                    phase: Phase::ApplyExtrinsic(0),
+                    event: Event::ExtrinsicSuccess,
+                },
+                EventRecord {
+                    phase: Phase::ApplyExtrinsic(1),
+                    event: Event::ExtrinsicFailed {
+                        error: DispatchError::BadOrigin
+                    },
+                },
+            ]
+        );
+
+        // Events are not part of the state, and are cleared when the next block starts.
+        let root = pallet.storage().root();
+        pallet.on_initialize(1);
+        assert_eq!(pallet.events(), &[]);
+        assert_eq!(pallet.storage().root(), root);
+    }
+
+    #[test]
+    fn state_root() {
+        let mut pallet = Pallet::<TestConfig>::new(&Storage::default());
+        assert_eq!(pallet.storage().root(), H256::default());
+
+        pallet.inc_nonce(&"Alice".to_string());
+        let root = pallet.storage().root();
+        assert_ne!(root, H256::default());
+
+        // The parent hash is not part of the state.
+        pallet.set_parent_hash(crate::support::hash_bytes(b"parent"));
+        assert_eq!(pallet.storage().root(), root);
+
+        pallet.inc_block_number();
+        assert_ne!(pallet.storage().root(), root);
     }
 }
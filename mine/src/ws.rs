@@ -0,0 +1,525 @@
+use crate::rpc::{self, Request};
+use crate::{system, types, RuntimeEvent};
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// The port the WebSocket server listens on, by default.
+pub const DEFAULT_WS_PORT: u16 = 9944;
+/// The number of messages which can wait to be sent to a client. A client which falls further
+/// behind is disconnected, so a slow client never holds up the node.
+const QUEUE_SIZE: usize = 256;
+/// The largest message accepted from a client.
+const MAX_MESSAGE_SIZE: usize = 1 << 20;
+/// Appended to the key of the client to compute the key of the handshake response.
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// How often the writer of a connection checks whether the connection must be closed.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+/// Close codes, sent when the server ends a connection.
+const CLOSE_NORMAL: u16 = 1000;
+const CLOSE_UNSUPPORTED: u16 = 1003;
+const CLOSE_POLICY: u16 = 1008;
+const CLOSE_TOO_BIG: u16 = 1009;
+
+/// The kinds of notifications a client can subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topic {
+    /// The header of every block which joins the best chain.
+    NewHeads,
+    /// The events of every block which joins the best chain, which include the outcome of each of
+    /// its extrinsics.
+    Events,
+}
+
+/// A message waiting to be sent to a client.
+enum Frame {
+    Text(String),
+    Pong(Vec<u8>),
+    Close(u16),
+}
+
+/// The queue of messages to be sent to a WebSocket client, which subscriptions send their
+/// notifications to.
+#[derive(Clone)]
+pub struct Sink {
+    connection: u64,
+    frames: SyncSender<Frame>,
+    /// Set when the queue is full, to have the connection closed.
+    overflowed: Arc<AtomicBool>,
+}
+
+/// The subscriptions of every connected client.
+#[derive(Default)]
+pub struct Subscriptions {
+    next_id: u64,
+    subscribers: BTreeMap<u64, (Topic, Sink)>,
+}
+
+impl Subscriptions {
+    /// Send the notifications of `topic` to `sink`. Returns the id of the subscription.
+    pub fn subscribe(&mut self, topic: Topic, sink: Sink) -> u64 {
+        self.next_id += 1;
+        self.subscribers.insert(self.next_id, (topic, sink));
+        self.next_id
+    }
+
+    /// Stop the subscription `id` to `topic`, which only the connection of `sink` can do. Returns
+    /// whether there was such a subscription.
+    pub fn unsubscribe(&mut self, topic: Topic, id: u64, sink: &Sink) -> bool {
+        match self.subscribers.get(&id) {
+            Some((t, s)) if *t == topic && s.connection == sink.connection => {
+                self.subscribers.remove(&id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Notify the subscribers that `header` joined the best chain, where executing it deposited
+    /// `events`.
+    pub fn notify_block(
+        &mut self,
+        header: &types::Header,
+        events: &[system::EventRecord<RuntimeEvent>],
+    ) {
+        self.notify(Topic::NewHeads, "chain_newHead", json!(header));
+        let events = json!({
+            "block": header.hash(),
+            "block_number": header.block_number,
+            "events": events,
+        });
+        self.notify(Topic::Events, "state_events", events);
+    }
+
+    /// Queue a notification for every subscriber to `topic`. The subscriptions of a connection
+    /// whose queue is full, or which is gone, are dropped.
+    fn notify(&mut self, topic: Topic, method: &str, result: Value) {
+        let mut dropped = Vec::new();
+        for (id, (t, sink)) in &self.subscribers {
+            if *t != topic || dropped.contains(&sink.connection) {
+                continue;
+            }
+            let notification = json!({
+                "jsonrpc": "2.0",
+                "method": method,
+                "params": { "subscription": id, "result": result },
+            });
+            match sink.frames.try_send(Frame::Text(notification.to_string())) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    sink.overflowed.store(true, Ordering::SeqCst);
+                    dropped.push(sink.connection);
+                }
+                Err(TrySendError::Disconnected(_)) => dropped.push(sink.connection),
+            }
+        }
+        self.subscribers
+            .retain(|_, (_, sink)| !dropped.contains(&sink.connection));
+    }
+}
+
+/// Listen for JSON-RPC calls over WebSocket on `port` of localhost, where port 0 picks any free
+/// port.
+///
+/// The calls are passed to the thread which owns the node through `requests`, like the calls of
+/// the HTTP server, and can also subscribe to notifications. Returns the address the server
+/// listens on.
+pub fn start(port: u16, requests: Sender<Request>) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    let addr = listener.local_addr()?;
+    let next_connection = AtomicU64::new(0);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let connection = next_connection.fetch_add(1, Ordering::SeqCst);
+            let requests = requests.clone();
+            thread::spawn(move || {
+                let _ = serve_connection(stream, connection, &requests);
+            });
+        }
+    });
+    Ok(addr)
+}
+
+/// Upgrade the connection to WebSocket, then answer the calls read from it until it is closed.
+///
+/// The messages sent to the client, answers and notifications alike, go through a bounded queue
+/// which is drained by a thread of its own.
+fn serve_connection(
+    stream: TcpStream,
+    connection: u64,
+    requests: &Sender<Request>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let Some(key) = read_handshake(&mut reader)? else {
+        return writer.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n");
+    };
+    let accept = base64(&Sha1::digest(format!("{}{}", key, HANDSHAKE_GUID)));
+    write!(
+        writer,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept
+    )?;
+
+    let (frames, queue) = mpsc::sync_channel(QUEUE_SIZE);
+    let sink = Sink {
+        connection,
+        frames,
+        overflowed: Arc::new(AtomicBool::new(false)),
+    };
+    let overflowed = sink.overflowed.clone();
+    thread::spawn(move || write_frames(writer, queue, &overflowed));
+
+    let close = loop {
+        let (opcode, payload) = match read_message(&mut reader) {
+            Ok(message) => message,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => break CLOSE_TOO_BIG,
+            Err(_) => break CLOSE_NORMAL,
+        };
+        let frame = match opcode {
+            OP_TEXT => match rpc::answer_all(&payload, requests, Some(&sink)) {
+                Some(response) => Frame::Text(response.to_string()),
+                None => continue,
+            },
+            OP_PING => Frame::Pong(payload),
+            OP_PONG => continue,
+            OP_CLOSE => break CLOSE_NORMAL,
+            _ => break CLOSE_UNSUPPORTED,
+        };
+        // Answers wait for room in the queue, since the client is waiting for them.
+        if sink.frames.send(frame).is_err() {
+            return Ok(());
+        }
+    };
+    if let Err(TrySendError::Full(_)) = sink.frames.try_send(Frame::Close(close)) {
+        sink.overflowed.store(true, Ordering::SeqCst);
+    }
+    Ok(())
+}
+
+/// Read the HTTP request which opens a connection. Returns the key of the client, if the request
+/// asks for an upgrade to WebSocket.
+fn read_handshake(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut key = None;
+    let mut upgrade = false;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "upgrade" => upgrade = value.eq_ignore_ascii_case("websocket"),
+                "sec-websocket-key" => key = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+    Ok(key.filter(|_| request_line.starts_with("GET ") && upgrade))
+}
+
+/// Send the queued messages to the client until the connection is closed by either side, or the
+/// queue overflows.
+fn write_frames(mut stream: TcpStream, queue: Receiver<Frame>, overflowed: &AtomicBool) {
+    loop {
+        if overflowed.load(Ordering::SeqCst) {
+            let _ = write_frame(&mut stream, OP_CLOSE, &close_payload(CLOSE_POLICY));
+            break;
+        }
+        let result = match queue.recv_timeout(POLL_INTERVAL) {
+            Ok(Frame::Text(text)) => write_frame(&mut stream, OP_TEXT, text.as_bytes()),
+            Ok(Frame::Pong(payload)) => write_frame(&mut stream, OP_PONG, &payload),
+            Ok(Frame::Close(code)) => {
+                let _ = write_frame(&mut stream, OP_CLOSE, &close_payload(code));
+                break;
+            }
+            Err(RecvTimeoutError::Timeout) => Ok(()),
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        if result.is_err() {
+            break;
+        }
+    }
+    // Dropping the queue ends the subscriptions of the connection, and the reader stops too.
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+/// Read the next message, joining its fragments. Control frames sent between the fragments are
+/// returned first.
+fn read_message(reader: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut message: Option<(u8, Vec<u8>)> = None;
+    loop {
+        let (fin, opcode, payload) = read_frame(reader)?;
+        if opcode >= OP_CLOSE {
+            return Ok((opcode, payload));
+        }
+        // Only the first fragment of a message has an opcode other than continuation.
+        if message.is_none() == (opcode == OP_CONTINUATION) {
+            return Err(io::Error::other("unexpected fragment"));
+        }
+        let (_, data) = message.get_or_insert((opcode, Vec::new()));
+        data.extend(payload);
+        if data.len() > MAX_MESSAGE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "message is too big",
+            ));
+        }
+        if fin {
+            return Ok(message.unwrap());
+        }
+    }
+}
+
+/// Read a single frame, and unmask its payload. Returns whether it is the last fragment of its
+/// message, its opcode, and its payload.
+fn read_frame(reader: &mut impl Read) -> io::Result<(bool, u8, Vec<u8>)> {
+    let mut head = [0u8; 2];
+    reader.read_exact(&mut head)?;
+    let fin = head[0] & 0x80 != 0;
+    let opcode = head[0] & 0x0F;
+    let len = match head[1] & 0x7F {
+        126 => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as u64
+        }
+        127 => {
+            let mut len = [0u8; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => len as u64,
+    };
+    if len > MAX_MESSAGE_SIZE as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message is too big",
+        ));
+    }
+    let mut mask = [0u8; 4];
+    if head[1] & 0x80 != 0 {
+        reader.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok((fin, opcode, payload))
+}
+
+/// Write `payload` as a single unmasked frame, as a server does.
+fn write_frame(stream: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend((len as u64).to_be_bytes());
+        }
+    }
+    frame.extend(payload);
+    stream.write_all(&frame)
+}
+
+fn close_payload(code: u16) -> [u8; 2] {
+    code.to_be_bytes()
+}
+
+/// Encode `bytes` in base64, with padding.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, byte)| n | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{Config, Node};
+    use crate::support::db::test_dir;
+    use crate::tests::{test_genesis, transfer};
+    use std::fs;
+
+    /// A client of the server, which masks its frames as clients must.
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        fn connect(addr: SocketAddr) -> Client {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(
+                stream,
+                "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                 Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+            )
+            .unwrap();
+            let mut response = Vec::new();
+            while !response.ends_with(b"\r\n\r\n") {
+                let mut byte = [0u8];
+                stream.read_exact(&mut byte).unwrap();
+                response.push(byte[0]);
+            }
+            let response = String::from_utf8(response).unwrap();
+            assert!(response.starts_with("HTTP/1.1 101"));
+            // The example of the WebSocket specification.
+            assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+            Client { stream }
+        }
+
+        fn send(&mut self, opcode: u8, payload: &[u8]) {
+            let mask = [1, 2, 3, 4];
+            let mut frame = vec![0x80 | opcode, 0x80 | payload.len() as u8];
+            frame.extend(mask);
+            frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+            self.stream.write_all(&frame).unwrap();
+        }
+
+        fn call(&mut self, call: Value) {
+            self.send(OP_TEXT, call.to_string().as_bytes());
+        }
+
+        fn receive(&mut self) -> (u8, Vec<u8>) {
+            read_message(&mut self.stream).unwrap()
+        }
+
+        fn receive_json(&mut self) -> Value {
+            let (opcode, payload) = self.receive();
+            assert_eq!(opcode, OP_TEXT);
+            serde_json::from_slice(&payload).unwrap()
+        }
+    }
+
+    #[test]
+    fn base64_matches_the_reference_encoding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn subscribers_are_notified_of_new_blocks() {
+        let dir = test_dir("ws_subscriptions");
+        let mut node = Node::open(&dir, &test_genesis(), Config::default()).unwrap();
+        let (sender, requests) = mpsc::channel();
+        let addr = start(0, sender).unwrap();
+        let serve_next = |node: &mut Node| {
+            let request = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+            rpc::serve(node, request);
+        };
+
+        let mut client = Client::connect(addr);
+        client.call(json!({"jsonrpc": "2.0", "id": 1, "method": "chain_subscribeNewHeads"}));
+        serve_next(&mut node);
+        let heads = client.receive_json()["result"].clone();
+        client.call(json!({"jsonrpc": "2.0", "id": 2, "method": "state_subscribeEvents"}));
+        serve_next(&mut node);
+        let events = client.receive_json()["result"].clone();
+
+        node.submit_extrinsic(transfer(node.runtime(), "Bob", 10))
+            .unwrap();
+        let block = node.author_block().unwrap();
+        let notification = client.receive_json();
+        assert_eq!(notification["method"], "chain_newHead");
+        assert_eq!(notification["params"]["subscription"], heads);
+        assert_eq!(notification["params"]["result"]["block_number"], 1);
+        let notification = client.receive_json();
+        assert_eq!(notification["method"], "state_events");
+        assert_eq!(notification["params"]["subscription"], events);
+        let result = &notification["params"]["result"];
+        assert_eq!(result["block"], json!(block.hash()));
+        let records = result["events"].as_array().unwrap();
+        assert!(records
+            .iter()
+            .any(|record| record["event"] == json!({"system": "ExtrinsicSuccess"})));
+
+        // Only the connection which subscribed can unsubscribe.
+        client.call(json!({
+            "jsonrpc": "2.0", "id": 3, "method": "state_unsubscribeEvents", "params": [heads]
+        }));
+        serve_next(&mut node);
+        assert_eq!(client.receive_json()["result"], false);
+        client.call(json!({
+            "jsonrpc": "2.0", "id": 4, "method": "chain_unsubscribeNewHeads", "params": [heads]
+        }));
+        serve_next(&mut node);
+        assert_eq!(client.receive_json()["result"], true);
+
+        node.author_block().unwrap();
+        let notification = client.receive_json();
+        assert_eq!(notification["method"], "state_events");
+        assert_eq!(notification["params"]["result"]["block_number"], 2);
+
+        client.send(OP_PING, b"hello");
+        assert_eq!(client.receive(), (OP_PONG, b"hello".to_vec()));
+        client.send(OP_CLOSE, &close_payload(CLOSE_NORMAL));
+        assert_eq!(
+            client.receive(),
+            (OP_CLOSE, close_payload(CLOSE_NORMAL).to_vec())
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn slow_subscribers_are_dropped() {
+        let (frames, queue) = mpsc::sync_channel(1);
+        let sink = Sink {
+            connection: 0,
+            frames,
+            overflowed: Arc::new(AtomicBool::new(false)),
+        };
+        let mut subscriptions = Subscriptions::default();
+        let id = subscriptions.subscribe(Topic::NewHeads, sink.clone());
+
+        subscriptions.notify(Topic::NewHeads, "chain_newHead", json!(1));
+        assert!(!sink.overflowed.load(Ordering::SeqCst));
+        // The client has not read the first notification, so there is no room for the second.
+        subscriptions.notify(Topic::NewHeads, "chain_newHead", json!(2));
+        assert!(sink.overflowed.load(Ordering::SeqCst));
+        assert!(!subscriptions.unsubscribe(Topic::NewHeads, id, &sink));
+        assert_eq!(queue.try_iter().count(), 1);
+    }
+}